@@ -1,33 +1,40 @@
 use std::{error::Error, sync::Arc};
 
-use once_cell::sync::Lazy;
-
-use piper::{synth::PiperSpeechSynthesizer, vits::VitsModel};
-
-static ENVIRONMENT: Lazy<Arc<ort::Environment>> =
-    Lazy::new(|| Arc::new(ort::Environment::default()));
+use piper::{
+    phonemizer::{ESpeakPhonemizer, SegmenterOptions},
+    synth::PiperSpeechSynthesizer,
+    vits::VitsModel,
+};
 
 fn main() -> Result<(), Box<dyn Error>> {
-    // let speaker = Arc::new(VitsModel::new(
+    // let speaker = Arc::new(VitsModel::builder(
     //     "piper-test/piper-voices/sv/sv_SE/nst/medium/sv_SE-nst-medium.onnx.json".into(),
     //     "piper-test/piper-voices/sv/sv_SE/nst/medium/sv_SE-nst-medium.onnx".into(),
-    //     &ENVIRONMENT,
-    // )?);
-    // let speaker = Arc::new(VitsModel::new(
+    // )
+    // .build()?);
+    // let speaker = Arc::new(VitsModel::builder(
     //     "piper-test/piper-voices/uk/uk_UA/ukrainian_tts/medium/uk_UA-ukrainian_tts-medium.onnx.json".into(),
     //     "piper-test/piper-voices/uk/uk_UA/ukrainian_tts/medium/uk_UA-ukrainian_tts-medium.onnx".into(),
-    //     &ENVIRONMENT,
-    // )?);
-    let speaker = Arc::new(VitsModel::new(
-        "piper-test/piper-voices/ru/ru_RU/irina/medium/ru_RU-irina-medium.onnx.json".into(),
-        "piper-test/piper-voices/ru/ru_RU/irina/medium/ru_RU-irina-medium.onnx".into(),
-        &ENVIRONMENT,
-    )?);
-    // let speaker = Arc::new(VitsModel::new(
+    // )
+    // .build()?);
+    // The Russian sample below is mostly unpunctuated, cap its sentences so they stay short enough to synthesize
+    let phonemizer = ESpeakPhonemizer::default().with_segmenter(SegmenterOptions {
+        max_sentence_phonemes: Some(400),
+        ..Default::default()
+    });
+    let speaker = Arc::new(
+        VitsModel::builder(
+            "piper-test/piper-voices/ru/ru_RU/irina/medium/ru_RU-irina-medium.onnx.json".into(),
+            "piper-test/piper-voices/ru/ru_RU/irina/medium/ru_RU-irina-medium.onnx".into(),
+        )
+        .phonemizer(Arc::new(phonemizer))
+        .build()?,
+    );
+    // let speaker = Arc::new(VitsModel::builder(
     //     "piper-test/piper-voices/de/de_DE/thorsten_emotional/medium/de_DE-thorsten_emotional-medium.onnx.json".into(),
     //     "piper-test/piper-voices/de/de_DE/thorsten_emotional/medium/de_DE-thorsten_emotional-medium.onnx".into(),
-    //     &ENVIRONMENT,
-    // )?);
+    // )
+    // .build()?);
     for speaker in speaker.speakers()? {
         println!("Speaker {}: {}", speaker.0, speaker.1);
     }
@@ -36,7 +43,6 @@ fn main() -> Result<(), Box<dyn Error>> {
     // speaker.set_speaker("whisper".to_string())?;
     // speaker.set_speaker("drunk".to_string())?;
 
-
     let synthesizer = PiperSpeechSynthesizer::new(speaker)?;
     // synthesizer.synthesize_to_wav_file("kaniner.wav", "Hej på dig min lilla kanin!".to_string())?;
     // synthesizer.synthesize_to_wav_file("kaniner.wav", "Весе́лка, також ра́йдуга оптичне явище в атмосфері, що являє собою одну, дві чи декілька різнокольорових дуг ,або кіл, якщо дивитися з повітря, що спостерігаються на тлі хмари, якщо вона розташована проти Сонця.".to_string())?;
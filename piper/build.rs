@@ -1,12 +1,74 @@
+#[cfg(feature = "bundled-data")]
+use std::fmt::Write as _;
+#[cfg(feature = "bundled-data")]
+use std::hash::Hasher;
+
+/// Overrides the `espeak-ng-data` directory that is embedded by the `bundled-data` feature
+#[cfg(feature = "bundled-data")]
+const PIPER_ESPEAKNG_DATA_SOURCE: &str = "PIPER_ESPEAKNG_DATA_SOURCE";
+/// Comma separated dictionaries to embed with the `bundled-data` feature (e.g. `en,ru`), all if unset
+#[cfg(feature = "bundled-data")]
+const PIPER_BUNDLED_LANGUAGES: &str = "PIPER_BUNDLED_LANGUAGES";
+
+/// Oldest eSpeak-ng release with `espeak_TextToPhonemesWithTerminator`, which 1.51 only has in piper's fork
+#[cfg(feature = "system-espeak")]
+const MIN_SYSTEM_ESPEAK_VERSION: &str = "1.52";
+
 fn main() {
-    let crate_path = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+    #[cfg(feature = "bundled-data")]
+    {
+        let data_source = match std::env::var(PIPER_ESPEAKNG_DATA_SOURCE) {
+            Ok(directory) => std::path::PathBuf::from(directory),
+            Err(_) => prebuilt_espeak_path().join("share/espeak-ng-data"),
+        };
+        generate_bundled_data(&data_source);
+    }
+
+    #[cfg(feature = "system-espeak")]
+    link_system_espeak();
+    #[cfg(all(feature = "static-espeak", not(feature = "system-espeak")))]
+    build_static_espeak();
+    #[cfg(not(any(feature = "static-espeak", feature = "system-espeak")))]
+    link_prebuilt_espeak();
+
+    #[cfg(feature = "bindgen")]
+    generate_bindings(&espeak_include_paths());
+}
+
+/// The `runtime-dependencies/espeak-ng` submodule
+#[cfg(all(
+    any(feature = "static-espeak", feature = "bindgen"),
+    not(feature = "system-espeak")
+))]
+fn espeak_source_path() -> std::path::PathBuf {
+    std::path::Path::new(&std::env::var("CARGO_MANIFEST_DIR").unwrap())
+        .join("runtime-dependencies/espeak-ng")
+}
 
-    let espeak_ng_path = format!(
-        "{}/runtime-dependencies/{}/espeak-ng-build/{}",
-        crate_path,
+/// Where `runtime-dependencies` has eSpeak-ng built for the target
+#[cfg(any(
+    feature = "bundled-data",
+    not(any(feature = "static-espeak", feature = "system-espeak"))
+))]
+fn prebuilt_espeak_path() -> std::path::PathBuf {
+    std::path::Path::new(&std::env::var("CARGO_MANIFEST_DIR").unwrap()).join(format!(
+        "runtime-dependencies/{}/espeak-ng-build/{}",
         build_target::target_os().unwrap(),
         build_target::target_arch().unwrap()
-    );
+    ))
+}
+
+/// Links the shared library from `runtime-dependencies` and copies it next to the binaries of the target directory
+#[cfg(not(any(feature = "static-espeak", feature = "system-espeak")))]
+fn link_prebuilt_espeak() {
+    let espeak_ng_path = prebuilt_espeak_path().display().to_string();
+    if !std::path::Path::new(&espeak_ng_path).is_dir() {
+        panic!(
+            "There is no prebuilt eSpeak-ng in `{}`, enable the `static-espeak` feature to build it from source \
+            or `system-espeak` to use the installed one",
+            espeak_ng_path
+        );
+    }
 
     println!("cargo:rustc-link-lib=espeak-ng");
     println!("cargo:rustc-link-search=native={}/lib", espeak_ng_path);
@@ -18,11 +80,200 @@ fn main() {
     #[cfg(target_os = "windows")]
     let espeak_ng_path = format!("{}/bin/espeak-ng.dll", espeak_ng_path);
     fs_extra::copy_items(
-        &[
-            espeak_ng_path,
-        ],
+        &[espeak_ng_path],
         format!("{}/../../..", std::env::var("OUT_DIR").unwrap()),
         &fs_extra::dir::CopyOptions::new().overwrite(true),
     )
     .unwrap();
 }
+
+/// Compiles the library from the `runtime-dependencies/espeak-ng` submodule with CMake and links it statically
+///
+/// Only the library is built, not `espeak-ng-data`, so this works for cross builds too.
+/// Optional components that pull in other libraries or threads are left out, piper only phonemizes.
+#[cfg(all(feature = "static-espeak", not(feature = "system-espeak")))]
+fn build_static_espeak() {
+    let source_path = espeak_source_path();
+    println!(
+        "cargo:rerun-if-changed={}",
+        source_path.join("src").display()
+    );
+    if !source_path.join("CMakeLists.txt").is_file() {
+        panic!(
+            "eSpeak-ng sources not found in `{}`, run `git submodule update --init`",
+            source_path.display()
+        );
+    }
+    let profile = "Release";
+    let destination = cmake::Config::new(&source_path)
+        .profile(profile)
+        .define("BUILD_SHARED_LIBS", "OFF")
+        .define("BUILD_TESTING", "OFF")
+        .define("USE_ASYNC", "OFF")
+        .define("USE_MBROLA", "OFF")
+        .define("USE_LIBSONIC", "OFF")
+        .define("USE_LIBPCAUDIO", "OFF")
+        .define("USE_KLATT", "OFF")
+        .define("USE_SPEECHPLAYER", "OFF")
+        .define("COMPILE_INTONATIONS", "OFF")
+        .build_target("espeak-ng")
+        .build();
+
+    // Multi-config generators (Visual Studio, Xcode) put the libraries into a directory named after the profile
+    let build_path = destination.join("build/src");
+    for library_path in [
+        build_path.join("libespeak-ng"),
+        build_path.join("ucd-tools"),
+    ] {
+        println!("cargo:rustc-link-search=native={}", library_path.display());
+        println!(
+            "cargo:rustc-link-search=native={}",
+            library_path.join(profile).display()
+        );
+    }
+    println!("cargo:rustc-link-lib=static=espeak-ng");
+    println!("cargo:rustc-link-lib=static=ucd");
+}
+
+/// Links the eSpeak-ng installed on the system as found by pkg-config
+#[cfg(feature = "system-espeak")]
+fn link_system_espeak() {
+    if let Err(e) = pkg_config::Config::new()
+        .atleast_version(MIN_SYSTEM_ESPEAK_VERSION)
+        .probe("espeak-ng")
+    {
+        panic!(
+            "eSpeak-ng {} or newer not found with pkg-config. {}",
+            MIN_SYSTEM_ESPEAK_VERSION, e
+        );
+    }
+}
+
+/// Directories to search for `espeak-ng/speak_lib.h` besides the default ones of clang
+#[cfg(feature = "bindgen")]
+fn espeak_include_paths() -> Vec<std::path::PathBuf> {
+    #[cfg(feature = "system-espeak")]
+    let include_paths = pkg_config::Config::new()
+        .cargo_metadata(false)
+        .probe("espeak-ng")
+        .map(|library| library.include_paths)
+        .unwrap_or_default();
+    #[cfg(all(feature = "static-espeak", not(feature = "system-espeak")))]
+    let include_paths = vec![espeak_source_path().join("src/include")];
+    #[cfg(not(any(feature = "static-espeak", feature = "system-espeak")))]
+    let include_paths = vec![
+        prebuilt_espeak_path().join("include"),
+        espeak_source_path().join("src/include"),
+    ];
+    include_paths
+}
+
+/// Writes `espeakng_bindings.rs` to `OUT_DIR`, generated from `speak_lib.h` of the linked eSpeak-ng
+#[cfg(feature = "bindgen")]
+fn generate_bindings(include_paths: &[std::path::PathBuf]) {
+    let bindings = bindgen::Builder::default()
+        .header_contents("wrapper.h", "#include <espeak-ng/speak_lib.h>")
+        .clang_args(
+            include_paths
+                .iter()
+                .map(|path| format!("-I{}", path.display())),
+        )
+        .allowlist_function("espeak_.*")
+        .allowlist_type("espeak_.*|t_espeak_callback")
+        .allowlist_var("espeak.*")
+        .parse_callbacks(Box::new(bindgen::CargoCallbacks::new()))
+        .generate()
+        .expect("Failed to generate the eSpeak-ng bindings, is libclang installed?");
+    bindings
+        .write_to_file(
+            std::path::Path::new(&std::env::var("OUT_DIR").unwrap()).join("espeakng_bindings.rs"),
+        )
+        .unwrap();
+}
+
+/// Writes `bundled_espeak_data.rs` to `OUT_DIR`, embedding the files of `data_source` with `include_bytes!`
+#[cfg(feature = "bundled-data")]
+fn generate_bundled_data(data_source: &std::path::Path) {
+    println!("cargo:rerun-if-env-changed={}", PIPER_ESPEAKNG_DATA_SOURCE);
+    println!("cargo:rerun-if-env-changed={}", PIPER_BUNDLED_LANGUAGES);
+    println!("cargo:rerun-if-changed={}", data_source.display());
+    if !data_source.join("phontab").is_file() {
+        panic!(
+            "`{}` is not an `espeak-ng-data` directory, set `{}` to the one to bundle",
+            data_source.display(),
+            PIPER_ESPEAKNG_DATA_SOURCE
+        );
+    }
+    let languages = std::env::var(PIPER_BUNDLED_LANGUAGES)
+        .ok()
+        .map(|languages| {
+            Vec::from_iter(
+                languages
+                    .split(',')
+                    .map(|language| language.trim().to_lowercase())
+                    .filter(|language| !language.is_empty()),
+            )
+        });
+
+    let mut files = Vec::new();
+    collect_files(data_source, data_source, &mut files);
+    files.retain(|(relative_path, _)| {
+        // Dictionaries make up most of the data, the rest is needed by every language
+        match (relative_path.strip_suffix("_dict"), &languages) {
+            (Some(dictionary), Some(languages)) if !relative_path.contains('/') => {
+                languages.iter().any(|language| language == dictionary)
+            }
+            _ => true,
+        }
+    });
+    files.sort();
+
+    // Extracted data is keyed by its content, so binaries bundling different data never share a directory
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    let mut generated =
+        String::from("pub(crate) static BUNDLED_DATA_FILES: &[(&str, &[u8])] = &[\n");
+    for (relative_path, path) in files.iter() {
+        hasher.write(relative_path.as_bytes());
+        hasher.write(&std::fs::read(path).unwrap());
+        writeln!(
+            generated,
+            "    ({:?}, include_bytes!({:?})),",
+            format!("espeak-ng-data/{}", relative_path),
+            path.display().to_string()
+        )
+        .unwrap();
+    }
+    generated.push_str("];\n");
+    writeln!(
+        generated,
+        "pub(crate) const BUNDLED_DATA_HASH: &str = \"{:016x}\";",
+        hasher.finish()
+    )
+    .unwrap();
+    let out_path =
+        std::path::Path::new(&std::env::var("OUT_DIR").unwrap()).join("bundled_espeak_data.rs");
+    std::fs::write(out_path, generated).unwrap();
+}
+
+/// Lists the files below `directory` with their path relative to `root`, using `/` as separator
+#[cfg(feature = "bundled-data")]
+fn collect_files(
+    root: &std::path::Path,
+    directory: &std::path::Path,
+    files: &mut Vec<(String, std::path::PathBuf)>,
+) {
+    for entry in std::fs::read_dir(directory).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            collect_files(root, &path, files);
+        } else {
+            let relative_path = Vec::from_iter(
+                path.strip_prefix(root)
+                    .unwrap()
+                    .components()
+                    .map(|component| component.as_os_str().to_string_lossy().into_owned()),
+            );
+            files.push((relative_path.join("/"), path));
+        }
+    }
+}
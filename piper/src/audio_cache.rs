@@ -0,0 +1,344 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::core::{PiperError, PiperResult, PiperWaveInfo, PiperWaveSamples, TextSpan};
+use crate::lru::SharedLruCache;
+use crate::synth::PiperSpeechSynthesizer;
+
+//----------------------------------------------------------------
+
+/// Starts the files written to the disk directory, the last byte is the format version
+const DISK_ENTRY_MAGIC: &[u8; 8] = b"PIPERAC1";
+
+/// Lookups and size of an [`AudioCache`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct AudioCacheStats {
+    pub memory_hits: u64,
+    /// Lookups answered from the disk directory after missing the memory
+    pub disk_hits: u64,
+    pub misses: u64,
+    /// Audio that could not be read from or written to the disk directory, it was synthesized or returned anyway
+    pub disk_errors: u64,
+    /// Texts currently held in memory
+    pub entries: usize,
+    pub capacity: usize,
+}
+
+/// Synthesizes text with a [`PiperSpeechSynthesizer`], reusing the audio of texts it synthesized before
+///
+/// Audio is keyed by the voice, speaker and synthesis parameters of the synthesizer's default model,
+/// see [`PiperModel::synthesis_fingerprint`](crate::core::PiperModel::synthesis_fingerprint), and the normalized text.
+/// The most recently used audio is kept in memory, and all of it in the disk directory if one is set,
+/// in files named by the hash of their key. Models without a fingerprint are not cached.
+///
+/// Piper voices add random noise to their speech that can not be seeded, so the cache synthesizes without it,
+/// see [`PiperModel::without_noise`](crate::core::PiperModel::without_noise). The same text then always gives
+/// the same audio, cached or not, but speech sounds flatter and less natural than with the model's own settings.
+/// The models given to the cache are not changed and keep their noise.
+pub struct AudioCache {
+    synthesizer: PiperSpeechSynthesizer,
+    memory: SharedLruCache<String, PiperWaveSamples>,
+    directory: Option<PathBuf>,
+    memory_hits: AtomicU64,
+    disk_hits: AtomicU64,
+    misses: AtomicU64,
+    disk_errors: AtomicU64,
+}
+
+impl AudioCache {
+    /// Keeps the audio of up to `capacity` texts in memory
+    pub fn new(synthesizer: PiperSpeechSynthesizer, capacity: usize) -> Self {
+        Self {
+            synthesizer: synthesizer.without_noise(),
+            memory: SharedLruCache::new(capacity),
+            directory: None,
+            memory_hits: AtomicU64::new(0),
+            disk_hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            disk_errors: AtomicU64::new(0),
+        }
+    }
+    /// Also stores audio in `directory`, which is created if missing and may be shared between processes
+    pub fn with_directory(mut self, directory: impl Into<PathBuf>) -> PiperResult<Self> {
+        let directory = directory.into();
+        fs::create_dir_all(&directory).map_err(|e| PiperError::Io {
+            message: format!(
+                "Failed to create audio cache directory `{}`",
+                directory.display()
+            ),
+            source: Some(e),
+        })?;
+        self.directory = Some(directory);
+        Ok(self)
+    }
+    /// The synthesizer without noise that the cache speaks with
+    pub fn synthesizer(&self) -> &PiperSpeechSynthesizer {
+        &self.synthesizer
+    }
+    /// Synthesizes the whole text into one piece of audio, or returns the cached audio of the same text
+    pub fn synthesize(&self, text: String) -> PiperResult<PiperWaveSamples> {
+        let source = Some(TextSpan::new(&text, 0..text.len()));
+        let Some(fingerprint) = self.synthesizer.synthesis_fingerprint() else {
+            self.misses.fetch_add(1, Ordering::Relaxed);
+            return Ok(self.synthesize_uncached(text)?.with_source(source));
+        };
+        let key = format!(
+            "{}\n{}",
+            fingerprint,
+            self.synthesizer.normalize_text(text.clone())
+        );
+        if let Some(wave) = self.memory.lock().get(&key) {
+            self.memory_hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(wave.clone().with_source(source));
+        }
+        let disk_path = self.disk_path(&key);
+        if let Some(ref path) = disk_path {
+            match read_disk_entry(path, &key) {
+                Ok(Some(wave)) => {
+                    self.disk_hits.fetch_add(1, Ordering::Relaxed);
+                    self.memory.lock().insert(key, wave.clone());
+                    return Ok(wave.with_source(source));
+                }
+                Ok(None) => {}
+                Err(_) => {
+                    self.disk_errors.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        }
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        let wave = self.synthesize_uncached(text)?;
+        // The disk directory only saves work, failing to fill it must not fail the synthesis
+        if let Some(ref path) = disk_path {
+            if write_disk_entry(path, &key, &wave).is_err() {
+                self.disk_errors.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        self.memory.lock().insert(key, wave.clone());
+        Ok(wave.with_source(source))
+    }
+    pub fn stats(&self) -> AudioCacheStats {
+        let memory = self.memory.lock();
+        AudioCacheStats {
+            memory_hits: self.memory_hits.load(Ordering::Relaxed),
+            disk_hits: self.disk_hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            disk_errors: self.disk_errors.load(Ordering::Relaxed),
+            entries: memory.len(),
+            capacity: memory.capacity(),
+        }
+    }
+    /// Drops the audio held in memory, the disk directory and the statistics are kept
+    pub fn clear(&self) {
+        self.memory.lock().clear();
+    }
+    fn synthesize_uncached(&self, text: String) -> PiperResult<PiperWaveSamples> {
+        let mut combined: Option<PiperWaveSamples> = None;
+        for wave in self.synthesizer.synthesize_parallel(text)? {
+            let wave = wave?;
+            match combined {
+                Some(ref mut combined) => {
+                    combined.samples.extend(wave.samples);
+                    combined.inference_ms = combined
+                        .inference_ms
+                        .zip(wave.inference_ms)
+                        .map(|(a, b)| a + b);
+                }
+                None => combined = Some(wave.with_source(None)),
+            }
+        }
+        combined.ok_or_else(|| PiperError::OperationError("No speech data to cache".to_string()))
+    }
+    fn disk_path(&self, key: &str) -> Option<PathBuf> {
+        let directory = self.directory.as_ref()?;
+        Some(directory.join(format!("{:016x}.pcm", fnv1a_hash(key.as_bytes()))))
+    }
+}
+
+/// FNV-1a, file names must stay the same across builds, which `DefaultHasher` does not promise
+fn fnv1a_hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Files hold their full key, so a hash collision or a damaged file is a miss instead of the wrong audio
+fn read_disk_entry(path: &Path, key: &str) -> io::Result<Option<PiperWaveSamples>> {
+    match fs::read(path) {
+        Ok(bytes) => Ok(decode_disk_entry(&bytes, key)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+fn write_disk_entry(path: &Path, key: &str, wave: &PiperWaveSamples) -> io::Result<()> {
+    // Written under a name of its own and renamed when done, so that other processes never read a partial file
+    let staging = path.with_extension(format!("{}.tmp", std::process::id()));
+    let result =
+        fs::write(&staging, encode_disk_entry(key, wave)).and_then(|_| fs::rename(&staging, path));
+    if result.is_err() {
+        fs::remove_file(&staging).ok();
+    }
+    result
+}
+
+/// Magic, key, sample rate, channels, sample width and the samples, numbers in little endian
+fn encode_disk_entry(key: &str, wave: &PiperWaveSamples) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(32 + key.len() + wave.samples.len() * 2);
+    bytes.extend_from_slice(DISK_ENTRY_MAGIC);
+    bytes.extend_from_slice(&(key.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(key.as_bytes());
+    bytes.extend_from_slice(&(wave.info.sample_rate as u32).to_le_bytes());
+    bytes.extend_from_slice(&(wave.info.num_channels as u16).to_le_bytes());
+    bytes.extend_from_slice(&(wave.info.sample_width as u16).to_le_bytes());
+    bytes.extend_from_slice(&(wave.samples.len() as u64).to_le_bytes());
+    bytes.extend(wave.samples.iter().flat_map(|sample| sample.to_le_bytes()));
+    bytes
+}
+
+fn decode_disk_entry(bytes: &[u8], key: &str) -> Option<PiperWaveSamples> {
+    let mut reader = EntryReader(bytes);
+    if reader.take(DISK_ENTRY_MAGIC.len())? != DISK_ENTRY_MAGIC {
+        return None;
+    }
+    let key_len = reader.u32()? as usize;
+    if reader.take(key_len)? != key.as_bytes() {
+        return None;
+    }
+    let info = PiperWaveInfo {
+        sample_rate: reader.u32()? as usize,
+        num_channels: reader.u16()? as usize,
+        sample_width: reader.u16()? as usize,
+    };
+    let num_samples = usize::try_from(reader.u64()?).ok()?;
+    let samples = Vec::from_iter(
+        reader
+            .take(num_samples.checked_mul(2)?)?
+            .chunks_exact(2)
+            .map(|sample| i16::from_le_bytes([sample[0], sample[1]])),
+    );
+    if !reader.0.is_empty() {
+        return None;
+    }
+    Some(PiperWaveSamples {
+        samples,
+        info,
+        inference_ms: None,
+        source: None,
+    })
+}
+
+struct EntryReader<'a>(&'a [u8]);
+
+impl<'a> EntryReader<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.0.len() < len {
+            return None;
+        }
+        let (taken, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Some(taken)
+    }
+    fn u16(&mut self) -> Option<u16> {
+        Some(u16::from_le_bytes(self.take(2)?.try_into().ok()?))
+    }
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.take(4)?.try_into().ok()?))
+    }
+    fn u64(&mut self) -> Option<u64> {
+        Some(u64::from_le_bytes(self.take(8)?.try_into().ok()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::PiperModel;
+    use crate::test_util::{temp_path, TestModel};
+    use std::sync::Arc;
+
+    fn cache_with(model: &Arc<TestModel>, capacity: usize) -> AudioCache {
+        let synthesizer = PiperSpeechSynthesizer::new(model.clone())
+            .unwrap()
+            .without_normalizer();
+        AudioCache::new(synthesizer, capacity)
+    }
+
+    #[test]
+    fn test_caches_in_memory() -> PiperResult<()> {
+        let model = Arc::new(TestModel::new(16000));
+        let cache = cache_with(&model, 1);
+        let first = cache.synthesize("hello".to_string())?;
+        let second = cache.synthesize("hello".to_string())?;
+        assert_eq!(first.samples, second.samples);
+        assert_eq!(
+            second.source.map(|source| source.text),
+            Some("hello".into())
+        );
+        assert!(!cache.synthesize("other".to_string())?.is_empty());
+        assert_eq!(
+            cache.synthesize("hello".to_string())?.samples,
+            first.samples
+        );
+        assert_eq!(model.sentences(), 3);
+        let stats = cache.stats();
+        assert_eq!((stats.memory_hits, stats.misses, stats.entries), (1, 3, 1));
+        Ok(())
+    }
+
+    #[test]
+    fn test_synthesizes_without_noise() -> PiperResult<()> {
+        let model = Arc::new(TestModel::new(16000));
+        let cache = cache_with(&model, 1);
+        assert_eq!(cache.synthesize("abc".to_string())?.samples[0], 0);
+        // The model given to the cache keeps its noise
+        assert_eq!(model.speak_one_sentence("abc".to_string())?.samples[0], 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_persists_to_disk() -> PiperResult<()> {
+        let directory = temp_path("audio-cache");
+        let model = Arc::new(TestModel::new(16000));
+        let written = cache_with(&model, 0)
+            .with_directory(&directory)?
+            .synthesize("hello".to_string())?;
+        // A new cache, as in another process, finds the audio on disk
+        let cache = cache_with(&model, 0).with_directory(&directory)?;
+        let read = cache.synthesize("hello".to_string())?;
+        assert_eq!(read.samples, written.samples);
+        assert_eq!(read.info.sample_rate, 16000);
+        assert_eq!(model.sentences(), 1);
+        // Damaged files are synthesized again
+        for entry in fs::read_dir(&directory).unwrap() {
+            fs::write(entry.unwrap().path(), b"PIPERAC1 damaged").unwrap();
+        }
+        assert_eq!(
+            cache.synthesize("hello".to_string())?.samples,
+            written.samples
+        );
+        fs::remove_dir_all(&directory).ok();
+        assert_eq!(model.sentences(), 2);
+        assert_eq!((cache.stats().disk_hits, cache.stats().misses), (1, 1));
+        Ok(())
+    }
+
+    #[test]
+    fn test_survives_unwritable_directory() -> PiperResult<()> {
+        let directory = temp_path("audio-cache-unwritable");
+        let model = Arc::new(TestModel::new(16000));
+        let cache = cache_with(&model, 0).with_directory(&directory)?;
+        // A file in place of the directory fails reads and writes alike, even for root
+        fs::remove_dir_all(&directory).unwrap();
+        fs::write(&directory, b"not a directory").unwrap();
+        let first = cache.synthesize("hello".to_string())?;
+        let second = cache.synthesize("hello".to_string())?;
+        fs::remove_file(&directory).ok();
+        assert_eq!(first.samples, second.samples);
+        assert!(!first.is_empty());
+        let stats = cache.stats();
+        assert_eq!((stats.misses, stats.disk_errors), (2, 4));
+        Ok(())
+    }
+}
@@ -0,0 +1,122 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+include!(concat!(env!("OUT_DIR"), "/bundled_espeak_data.rs"));
+
+//----------------------------------------------------------------
+
+/// Name of the environment variable that overrides the directory the bundled `espeak-ng-data` is extracted to
+const PIPER_ESPEAKNG_CACHE_DIRECTORY: &str = "PIPER_ESPEAKNG_CACHE_DIRECTORY";
+
+/// Written last, marks a directory whose extraction completed
+const EXTRACTED_MARKER: &str = ".extracted";
+
+/// Extracts the bundled `espeak-ng-data` to the cache directory unless it is there already,
+/// returning the directory that contains `espeak-ng-data`
+pub(crate) fn extract_bundled_data() -> io::Result<PathBuf> {
+    extract_to(&cache_directory())
+}
+
+fn extract_to(cache_directory: &Path) -> io::Result<PathBuf> {
+    let directory = cache_directory.join(format!("espeak-ng-data-{}", BUNDLED_DATA_HASH));
+    if directory.join(EXTRACTED_MARKER).is_file() {
+        return Ok(directory);
+    }
+    // Incomplete, e.g. partially removed by a cache cleaner
+    if directory.exists() {
+        fs::remove_dir_all(&directory)?;
+    }
+    // Extracted under a name of its own and renamed when done,
+    // so that processes starting at the same time never load a partially written directory
+    let staging = staging_directory(cache_directory);
+    if let Err(e) = write_files(&staging) {
+        fs::remove_dir_all(&staging).ok();
+        return Err(e);
+    }
+    match fs::rename(&staging, &directory) {
+        Ok(()) => Ok(directory),
+        // Another process finished first
+        Err(_) if directory.join(EXTRACTED_MARKER).is_file() => {
+            fs::remove_dir_all(&staging).ok();
+            Ok(directory)
+        }
+        Err(e) => {
+            fs::remove_dir_all(&staging).ok();
+            Err(e)
+        }
+    }
+}
+
+fn staging_directory(cache_directory: &Path) -> PathBuf {
+    cache_directory.join(format!(
+        "espeak-ng-data-{}.{}",
+        BUNDLED_DATA_HASH,
+        std::process::id()
+    ))
+}
+
+fn write_files(staging: &Path) -> io::Result<()> {
+    for (relative_path, contents) in BUNDLED_DATA_FILES {
+        let path = staging.join(relative_path);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, contents)?;
+    }
+    fs::write(staging.join(EXTRACTED_MARKER), BUNDLED_DATA_HASH)
+}
+
+/// The per user cache directory of the platform, or the temporary directory if there is none
+fn cache_directory() -> PathBuf {
+    if let Some(directory) = env::var_os(PIPER_ESPEAKNG_CACHE_DIRECTORY) {
+        return PathBuf::from(directory);
+    }
+    let home = || env::var_os("HOME").map(PathBuf::from);
+    let platform_cache = if cfg!(target_os = "windows") {
+        env::var_os("LOCALAPPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        home().map(|home| home.join("Library/Caches"))
+    } else {
+        env::var_os("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| home().map(|home| home.join(".cache")))
+    };
+    platform_cache
+        .unwrap_or_else(env::temp_dir)
+        .join("piper-rs")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_path;
+
+    #[test]
+    fn test_extracts_once() -> io::Result<()> {
+        let cache_directory = temp_path("bundled");
+        let directory = extract_to(&cache_directory)?;
+        for (relative_path, contents) in BUNDLED_DATA_FILES {
+            assert_eq!(fs::read(directory.join(relative_path))?, *contents);
+        }
+        // A second extraction reuses the directory
+        let phontab = directory.join("espeak-ng-data/phontab");
+        fs::write(&phontab, b"modified")?;
+        assert_eq!(extract_to(&cache_directory)?, directory);
+        assert_eq!(fs::read(&phontab)?, b"modified");
+        fs::remove_dir_all(&cache_directory)
+    }
+
+    #[test]
+    fn test_removes_partial_extraction() -> io::Result<()> {
+        let cache_directory = temp_path("bundled-partial");
+        // A directory in place of a data file fails its write after the staging directory was created
+        let staging = staging_directory(&cache_directory);
+        let (relative_path, _) = BUNDLED_DATA_FILES[0];
+        fs::create_dir_all(staging.join(relative_path))?;
+        assert!(extract_to(&cache_directory).is_err());
+        assert!(!staging.exists());
+        fs::remove_dir_all(&cache_directory)
+    }
+}
@@ -1,65 +1,272 @@
 use std::error::Error;
 use std::fmt;
+use std::io;
+use std::ops::Range;
+use std::path::PathBuf;
+use std::sync::Arc;
 
 use crate::wave_writer;
 
+pub use crate::phonemize::ESpeakError;
+
 pub type PiperResult<T> = Result<T, PiperError>;
 pub type PiperWaveResult = PiperResult<PiperWaveSamples>;
 
+/// Broad category of a [`PiperError`], for callers that need to react to failures programmatically
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PiperErrorKind {
+    VoiceNotFound,
+    InvalidConfig,
+    InvalidSpeaker,
+    PhonemizationFailed,
+    UnknownPhonemes,
+    InferenceFailed,
+    Io,
+    UnsupportedFormat,
+    Cancelled,
+    /// Input that can't be parsed, such as malformed SSML
+    InvalidInput,
+    OperationFailed,
+}
+
 #[derive(Debug)]
 pub enum PiperError {
-    FailedToLoadResource(String),
-    PhonemizationError(String),
+    /// The voice config or onnx file does not exist or can not be opened
+    VoiceNotFound {
+        path: PathBuf,
+        source: Option<io::Error>,
+    },
+    /// The voice config is malformed or does not match the onnx graph
+    InvalidConfig {
+        message: String,
+        source: Option<serde_json::Error>,
+    },
+    InvalidSpeaker(String),
+    PhonemizationFailed(ESpeakError),
+    /// Phonemes missing from the voice's `phoneme_id_map`, reported by models in strict mode
+    UnknownPhonemes(Vec<String>),
+    InferenceFailed {
+        message: String,
+        source: Option<ort::OrtError>,
+    },
+    Io {
+        message: String,
+        source: Option<io::Error>,
+    },
+    UnsupportedFormat(String),
+    /// Synthesis was stopped through a cancellation token
+    Cancelled,
+    /// The SSML given for synthesis is malformed
+    InvalidSsml(String),
+    /// Synthesis of a sentence failed, `span` is the text it was produced from
+    SentenceFailed {
+        span: TextSpan,
+        source: Box<PiperError>,
+    },
     OperationError(String),
 }
 
-impl Error for PiperError {}
+impl PiperError {
+    pub fn kind(&self) -> PiperErrorKind {
+        match self {
+            PiperError::VoiceNotFound { .. } => PiperErrorKind::VoiceNotFound,
+            PiperError::InvalidConfig { .. } => PiperErrorKind::InvalidConfig,
+            PiperError::InvalidSpeaker(_) => PiperErrorKind::InvalidSpeaker,
+            PiperError::PhonemizationFailed(_) => PiperErrorKind::PhonemizationFailed,
+            PiperError::UnknownPhonemes(_) => PiperErrorKind::UnknownPhonemes,
+            PiperError::InferenceFailed { .. } => PiperErrorKind::InferenceFailed,
+            PiperError::Io { .. } => PiperErrorKind::Io,
+            PiperError::UnsupportedFormat(_) => PiperErrorKind::UnsupportedFormat,
+            PiperError::Cancelled => PiperErrorKind::Cancelled,
+            PiperError::InvalidSsml(_) => PiperErrorKind::InvalidInput,
+            PiperError::SentenceFailed { source, .. } => source.kind(),
+            PiperError::OperationError(_) => PiperErrorKind::OperationFailed,
+        }
+    }
+}
+
+impl Error for PiperError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            PiperError::VoiceNotFound {
+                source: Some(e), ..
+            } => Some(e),
+            PiperError::InvalidConfig {
+                source: Some(e), ..
+            } => Some(e),
+            PiperError::PhonemizationFailed(e) => Some(e),
+            PiperError::InferenceFailed {
+                source: Some(e), ..
+            } => Some(e),
+            PiperError::Io {
+                source: Some(e), ..
+            } => Some(e),
+            PiperError::SentenceFailed { source, .. } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
 
 impl fmt::Display for PiperError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let err_message = match self {
-            PiperError::FailedToLoadResource(msg) => {
-                format!("Failed to load resource from. Error `{}`", msg)
+        match self {
+            PiperError::VoiceNotFound { path, .. } => {
+                write!(f, "Voice not found: `{}`", path.display())
             }
-            PiperError::PhonemizationError(msg) => msg.to_string(),
-            PiperError::OperationError(msg) => msg.to_string(),
-        };
-        write!(f, "{}", err_message)
+            PiperError::InvalidConfig { message, .. } => {
+                write!(f, "Invalid voice config. {}", message)
+            }
+            PiperError::InvalidSpeaker(msg) => write!(f, "Invalid speaker. {}", msg),
+            PiperError::PhonemizationFailed(_) => write!(f, "Failed to phonemize text"),
+            PiperError::UnknownPhonemes(phonemes) => {
+                let phonemes = Vec::from_iter(phonemes.iter().map(|p| format!("`{}`", p)));
+                write!(f, "Unknown phonemes: {}", phonemes.join(", "))
+            }
+            PiperError::InferenceFailed { message, .. } => write!(f, "{}", message),
+            PiperError::Io { message, .. } => write!(f, "{}", message),
+            PiperError::UnsupportedFormat(msg) => write!(f, "Unsupported format. {}", msg),
+            PiperError::Cancelled => write!(f, "Synthesis was cancelled"),
+            PiperError::InvalidSsml(msg) => write!(f, "Invalid SSML. {}", msg),
+            PiperError::SentenceFailed { span, .. } => write!(
+                f,
+                "Failed to synthesize `{}` (bytes {}..{})",
+                span.text, span.bytes.start, span.bytes.end
+            ),
+            PiperError::OperationError(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl From<ESpeakError> for PiperError {
+    fn from(error: ESpeakError) -> Self {
+        PiperError::PhonemizationFailed(error)
     }
 }
 
 impl From<wave_writer::WaveWriterError> for PiperError {
     fn from(error: wave_writer::WaveWriterError) -> Self {
-        PiperError::OperationError(error.to_string())
+        match error {
+            wave_writer::WaveWriterError::UnsupportedFormat(msg) => {
+                PiperError::UnsupportedFormat(msg)
+            }
+            wave_writer::WaveWriterError::Io { message, source } => {
+                PiperError::Io { message, source }
+            }
+        }
+    }
+}
+
+/// Part of the input text that a sentence was produced from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextSpan {
+    pub text: String,
+    /// Byte offsets into the input text
+    pub bytes: Range<usize>,
+    /// Char offsets into the input text, for UIs that index text by character
+    pub chars: Range<usize>,
+}
+
+impl TextSpan {
+    /// Span of the byte range `bytes` of `source`, the range must lie on char boundaries
+    pub fn new(source: &str, bytes: Range<usize>) -> Self {
+        let text = source[bytes.clone()].to_string();
+        let char_start = source[..bytes.start].chars().count();
+        let chars = char_start..char_start + text.chars().count();
+        Self { text, bytes, chars }
     }
 }
 
-/// A wrapper type that holds sentence phonemes
-pub struct Phonemes(pub Vec<String>);
+/// A wrapper type that holds sentence phonemes, along with the text each sentence was produced from if known
+#[derive(Debug, Clone, Default)]
+pub struct Phonemes {
+    sentences: Vec<String>,
+    sources: Vec<Option<TextSpan>>,
+}
 
 impl Phonemes {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, phonemes: String, source: Option<TextSpan>) {
+        self.sentences.push(phonemes);
+        self.sources.push(source);
+    }
+
     pub fn sentences(&self) -> &Vec<String> {
-        &self.0
+        &self.sentences
+    }
+
+    /// Source text of each sentence, in the same order as [`Phonemes::sentences`]
+    pub fn sources(&self) -> &Vec<Option<TextSpan>> {
+        &self.sources
     }
 
     pub fn to_vec(self) -> Vec<String> {
-        self.0
+        self.sentences
     }
 
     pub fn num_sentences(&self) -> usize {
-        self.0.len()
+        self.sentences.len()
+    }
+
+    /// Replaces the source of every sentence that has one, e.g. to make it relative to a larger text
+    pub fn map_sources(mut self, mut f: impl FnMut(TextSpan) -> Option<TextSpan>) -> Self {
+        self.sources = Vec::from_iter(
+            self.sources
+                .into_iter()
+                .map(|source| source.and_then(&mut f)),
+        );
+        self
     }
 }
 
 impl From<Vec<String>> for Phonemes {
     fn from(other: Vec<String>) -> Self {
-        Self(other)
+        Self {
+            sources: vec![None; other.len()],
+            sentences: other,
+        }
     }
 }
 
-impl std::string::ToString for Phonemes {
-    fn to_string(&self) -> String {
-        self.0.join(" ")
+impl FromIterator<(String, Option<TextSpan>)> for Phonemes {
+    fn from_iter<I: IntoIterator<Item = (String, Option<TextSpan>)>>(iter: I) -> Self {
+        let (sentences, sources) = iter.into_iter().unzip();
+        Self { sentences, sources }
+    }
+}
+
+impl IntoIterator for Phonemes {
+    type Item = (String, Option<TextSpan>);
+    type IntoIter =
+        std::iter::Zip<std::vec::IntoIter<String>, std::vec::IntoIter<Option<TextSpan>>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.sentences.into_iter().zip(self.sources)
+    }
+}
+
+impl fmt::Display for Phonemes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.sentences.join(" "))
+    }
+}
+
+/// Text in a single language, `None` stands for the language of the synthesizer's default voice
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextSegment {
+    pub text: String,
+    /// eSpeak-ng voice name or BCP 47 tag, e.g. `en-us` or `ru`
+    pub language: Option<String>,
+}
+
+impl TextSegment {
+    pub fn new(text: impl Into<String>, language: Option<&str>) -> Self {
+        Self {
+            text: text.into(),
+            language: language.map(str::to_string),
+        }
     }
 }
 
@@ -76,6 +283,8 @@ pub struct PiperWaveSamples {
     pub samples: Vec<i16>,
     pub info: PiperWaveInfo,
     pub inference_ms: Option<f32>,
+    /// Text the samples were synthesized from, set by the speech streams
+    pub source: Option<TextSpan>,
 }
 
 impl PiperWaveSamples {
@@ -88,9 +297,15 @@ impl PiperWaveSamples {
                 num_channels: 1,
                 sample_width: 2,
             },
+            source: None,
         }
     }
 
+    pub fn with_source(mut self, source: Option<TextSpan>) -> Self {
+        self.source = source;
+        self
+    }
+
     pub fn to_vec(self) -> Vec<i16> {
         self.samples
     }
@@ -116,9 +331,7 @@ impl PiperWaveSamples {
     }
 
     pub fn real_time_factor(&self) -> Option<f32> {
-        let Some(infer_ms) = self.inference_ms else {
-            return None;
-        };
+        let infer_ms = self.inference_ms?;
         let audio_duration = self.duration_ms();
         if audio_duration == 0. {
             return Some(0.);
@@ -126,6 +339,58 @@ impl PiperWaveSamples {
         Some(infer_ms / audio_duration)
     }
 
+    /// Converts the samples to another sample rate with windowed sinc interpolation
+    ///
+    /// Frequencies above the Nyquist frequency of the lower of the two rates are filtered out, so downsampling doesn't alias.
+    pub fn resample(self, sample_rate: usize) -> Self {
+        let source_rate = self.info.sample_rate;
+        if source_rate == sample_rate || source_rate == 0 || self.samples.is_empty() {
+            return Self {
+                info: PiperWaveInfo {
+                    sample_rate,
+                    ..self.info
+                },
+                ..self
+            };
+        }
+        let ratio = source_rate as f64 / sample_rate as f64;
+        let num_samples = (self.samples.len() as f64 / ratio).round() as usize;
+        // Cutoff as a fraction of the source's Nyquist frequency, a little below the target's to leave room for the transition
+        let cutoff = ratio.recip().min(1.0) * 0.95;
+        let half_width = (RESAMPLE_ZERO_CROSSINGS / cutoff).ceil() as i64;
+        let samples = Vec::from_iter((0..num_samples).map(|i| {
+            let position = i as f64 * ratio;
+            let center = position.floor() as i64;
+            let first = (center - half_width + 1).max(0);
+            let last = (center + half_width).min(self.samples.len() as i64 - 1);
+            let (mut sum, mut weights) = (0.0, 0.0);
+            for index in first..=last {
+                let distance = position - index as f64;
+                let window =
+                    0.5 + 0.5 * (std::f64::consts::PI * distance / half_width as f64).cos();
+                let weight = sinc(cutoff * distance) * window;
+                sum += self.samples[index as usize] as f64 * weight;
+                weights += weight;
+            }
+            // Normalized so that the level is kept at the edges, where part of the window is missing
+            let value = if weights.abs() > f64::EPSILON {
+                sum / weights
+            } else {
+                0.0
+            };
+            value.round().clamp(i16::MIN as f64, i16::MAX as f64) as i16
+        }));
+        Self {
+            samples,
+            info: PiperWaveInfo {
+                sample_rate,
+                ..self.info
+            },
+            inference_ms: self.inference_ms,
+            source: self.source,
+        }
+    }
+
     pub fn save_to_file(&self, filename: &str) -> PiperResult<()> {
         Ok(wave_writer::write_wave_samples_to_file(
             filename.into(),
@@ -137,6 +402,18 @@ impl PiperWaveSamples {
     }
 }
 
+/// Zero crossings of the resampling filter on each side, trading sharpness of the cutoff against speed
+const RESAMPLE_ZERO_CROSSINGS: f64 = 16.0;
+
+/// Normalized sinc, `sin(πx) / πx`
+fn sinc(x: f64) -> f64 {
+    if x.abs() < 1e-9 {
+        return 1.0;
+    }
+    let x = std::f64::consts::PI * x;
+    x.sin() / x
+}
+
 impl IntoIterator for PiperWaveSamples {
     type Item = i16;
     type IntoIter = std::vec::IntoIter<Self::Item>;
@@ -148,7 +425,67 @@ impl IntoIterator for PiperWaveSamples {
 
 pub trait PiperModel {
     fn phonemize_text(&self, text: &str) -> PiperResult<Phonemes>;
+    /// Phonemizes text written in another language than the model's own, models that can't switch languages ignore it
+    fn phonemize_text_with_language(&self, text: &str, _language: &str) -> PiperResult<Phonemes> {
+        self.phonemize_text(text)
+    }
     fn speak_batch(&self, phoneme_batches: Vec<String>) -> PiperResult<Vec<PiperWaveSamples>>;
     fn speak_one_sentence(&self, phonemes: String) -> PiperWaveResult;
     fn wave_info(&self) -> PiperResult<PiperWaveInfo>;
+    /// eSpeak-ng voice name of the model's language, used to pick text normalization rules
+    fn language(&self) -> Option<String> {
+        None
+    }
+    /// Identifies the voice, speaker and synthesis parameters, equal fingerprints synthesize the same text alike
+    ///
+    /// Output of models without one is not cached by [`AudioCache`](crate::audio_cache::AudioCache),
+    /// which synthesizes with [`PiperModel::without_noise`] so that equal fingerprints also give equal samples.
+    fn synthesis_fingerprint(&self) -> Option<String> {
+        None
+    }
+    /// The same voice and settings synthesizing without noise, so the same phonemes give the same samples every time,
+    /// at the cost of flatter, less natural speech. This model keeps its noise.
+    ///
+    /// Models whose output has no randomness to begin with return `None`.
+    fn without_noise(&self) -> Option<Arc<dyn PiperModel + Sync + Send>> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tone(frequency: f64, sample_rate: usize, len: usize) -> PiperWaveSamples {
+        let samples = Vec::from_iter((0..len).map(|i| {
+            let phase = 2.0 * std::f64::consts::PI * frequency * i as f64 / sample_rate as f64;
+            (phase.sin() * 10000.0) as i16
+        }));
+        PiperWaveSamples::new(samples, sample_rate, None)
+    }
+
+    /// Root mean square of the samples, leaving out the edges
+    fn level(wave: &PiperWaveSamples) -> f64 {
+        let samples = &wave.samples[100..wave.len() - 100];
+        let power = samples.iter().map(|s| (*s as f64).powi(2)).sum::<f64>() / samples.len() as f64;
+        power.sqrt()
+    }
+
+    #[test]
+    fn test_resample_filters_aliases() {
+        let passed = tone(1000.0, 22050, 22050);
+        let original = level(&passed);
+        let resampled = passed.resample(16000);
+        assert_eq!(
+            (resampled.len(), resampled.info.sample_rate),
+            (16000, 16000)
+        );
+        assert!((level(&resampled) / original - 1.0).abs() < 0.02);
+        // 10 kHz is above the 8 kHz Nyquist frequency of the target and would fold back to 6 kHz
+        let aliased = tone(10000.0, 22050, 22050).resample(16000);
+        assert!(level(&aliased) < original * 0.02, "{}", level(&aliased));
+        // Upsampling keeps the tone
+        let upsampled = tone(1000.0, 16000, 16000).resample(22050);
+        assert!((level(&upsampled) / original - 1.0).abs() < 0.02);
+    }
 }
@@ -0,0 +1,263 @@
+/* Generated by rust-bindgen 0.69.4 from `src/libespeak-ng/include/espeak-ng/speak_lib.h` of
+ * eSpeak-ng 1.52.0, which has `espeak_TextToPhonemesWithTerminator` (1.51 only has it in piper's fork),
+ * with the allowlists of `generate_bindings` in `build.rs`, then edited by hand to build on every platform:
+ * - `wchar_t` is 16 bits on Windows and 32 bits elsewhere instead of the generating host's type
+ * - C enums are `c_uint` everywhere, MSVC would make them `c_int`, which has the same size and alignment
+ * - the layout tests of the structs are left out, they only hold on the generating host
+ * The `bindgen` feature generates them from the linked library's header instead. */
+
+#[cfg(windows)]
+pub type wchar_t = ::std::os::raw::c_ushort;
+#[cfg(not(windows))]
+pub type wchar_t = ::std::os::raw::c_int;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct FILE {
+    _unused: [u8; 0],
+}
+
+pub const espeakRATE_MINIMUM: u32 = 80;
+pub const espeakRATE_MAXIMUM: u32 = 450;
+pub const espeakRATE_NORMAL: u32 = 175;
+
+pub const espeakINITIALIZE_PHONEME_EVENTS: u32 = 1;
+pub const espeakINITIALIZE_PHONEME_IPA: u32 = 2;
+pub const espeakINITIALIZE_DONT_EXIT: u32 = 32768;
+
+pub const espeakCHARS_AUTO: u32 = 0;
+pub const espeakCHARS_UTF8: u32 = 1;
+pub const espeakCHARS_8BIT: u32 = 2;
+pub const espeakCHARS_WCHAR: u32 = 3;
+pub const espeakCHARS_16BIT: u32 = 4;
+
+pub const espeakSSML: u32 = 16;
+pub const espeakPHONEMES: u32 = 256;
+pub const espeakENDPAUSE: u32 = 4096;
+pub const espeakKEEP_NAME: u32 = 8192;
+
+pub const espeakPHONEMES_SHOW: u32 = 1;
+pub const espeakPHONEMES_IPA: u32 = 2;
+pub const espeakPHONEMES_TRACE: u32 = 8;
+pub const espeakPHONEMES_MBROLA: u32 = 16;
+pub const espeakPHONEMES_TIE: u32 = 128;
+
+pub type espeak_EVENT_TYPE = ::std::os::raw::c_uint;
+pub const espeak_EVENT_TYPE_espeakEVENT_LIST_TERMINATED: espeak_EVENT_TYPE = 0;
+pub const espeak_EVENT_TYPE_espeakEVENT_WORD: espeak_EVENT_TYPE = 1;
+pub const espeak_EVENT_TYPE_espeakEVENT_SENTENCE: espeak_EVENT_TYPE = 2;
+pub const espeak_EVENT_TYPE_espeakEVENT_MARK: espeak_EVENT_TYPE = 3;
+pub const espeak_EVENT_TYPE_espeakEVENT_PLAY: espeak_EVENT_TYPE = 4;
+pub const espeak_EVENT_TYPE_espeakEVENT_END: espeak_EVENT_TYPE = 5;
+pub const espeak_EVENT_TYPE_espeakEVENT_MSG_TERMINATED: espeak_EVENT_TYPE = 6;
+pub const espeak_EVENT_TYPE_espeakEVENT_PHONEME: espeak_EVENT_TYPE = 7;
+pub const espeak_EVENT_TYPE_espeakEVENT_SAMPLERATE: espeak_EVENT_TYPE = 8;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub union espeak_EVENT__bindgen_ty_1 {
+    /// Used for WORD and SENTENCE events
+    pub number: ::std::os::raw::c_int,
+    /// Used for MARK and PLAY events. UTF8 string
+    pub name: *const ::std::os::raw::c_char,
+    /// Used for phoneme names (UTF8). Terminated by a zero byte unless the name needs the full 8 bytes
+    pub string: [::std::os::raw::c_char; 8usize],
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct espeak_EVENT {
+    pub type_: espeak_EVENT_TYPE,
+    /// Message identifier (or 0 for key or character)
+    pub unique_identifier: ::std::os::raw::c_uint,
+    /// The number of characters from the start of the text
+    pub text_position: ::std::os::raw::c_int,
+    /// Word length, in characters (for espeakEVENT_WORD)
+    pub length: ::std::os::raw::c_int,
+    /// The time in mS within the generated speech output data
+    pub audio_position: ::std::os::raw::c_int,
+    /// Sample id (internal use)
+    pub sample: ::std::os::raw::c_int,
+    /// Pointer supplied by the calling program
+    pub user_data: *mut ::std::os::raw::c_void,
+    pub id: espeak_EVENT__bindgen_ty_1,
+}
+
+pub type espeak_POSITION_TYPE = ::std::os::raw::c_uint;
+pub const espeak_POSITION_TYPE_POS_CHARACTER: espeak_POSITION_TYPE = 1;
+pub const espeak_POSITION_TYPE_POS_WORD: espeak_POSITION_TYPE = 2;
+pub const espeak_POSITION_TYPE_POS_SENTENCE: espeak_POSITION_TYPE = 3;
+
+pub type espeak_AUDIO_OUTPUT = ::std::os::raw::c_uint;
+pub const espeak_AUDIO_OUTPUT_AUDIO_OUTPUT_PLAYBACK: espeak_AUDIO_OUTPUT = 0;
+pub const espeak_AUDIO_OUTPUT_AUDIO_OUTPUT_RETRIEVAL: espeak_AUDIO_OUTPUT = 1;
+pub const espeak_AUDIO_OUTPUT_AUDIO_OUTPUT_SYNCHRONOUS: espeak_AUDIO_OUTPUT = 2;
+pub const espeak_AUDIO_OUTPUT_AUDIO_OUTPUT_SYNCH_PLAYBACK: espeak_AUDIO_OUTPUT = 3;
+
+pub type espeak_ERROR = ::std::os::raw::c_int;
+pub const espeak_ERROR_EE_OK: espeak_ERROR = 0;
+pub const espeak_ERROR_EE_INTERNAL_ERROR: espeak_ERROR = -1;
+pub const espeak_ERROR_EE_BUFFER_FULL: espeak_ERROR = 1;
+pub const espeak_ERROR_EE_NOT_FOUND: espeak_ERROR = 2;
+
+pub type t_espeak_callback = ::std::option::Option<
+    unsafe extern "C" fn(
+        wav: *mut ::std::os::raw::c_short,
+        numsamples: ::std::os::raw::c_int,
+        events: *mut espeak_EVENT,
+    ) -> ::std::os::raw::c_int,
+>;
+
+pub type espeak_PARAMETER = ::std::os::raw::c_uint;
+pub const espeak_PARAMETER_espeakSILENCE: espeak_PARAMETER = 0;
+pub const espeak_PARAMETER_espeakRATE: espeak_PARAMETER = 1;
+pub const espeak_PARAMETER_espeakVOLUME: espeak_PARAMETER = 2;
+pub const espeak_PARAMETER_espeakPITCH: espeak_PARAMETER = 3;
+pub const espeak_PARAMETER_espeakRANGE: espeak_PARAMETER = 4;
+pub const espeak_PARAMETER_espeakPUNCTUATION: espeak_PARAMETER = 5;
+pub const espeak_PARAMETER_espeakCAPITALS: espeak_PARAMETER = 6;
+pub const espeak_PARAMETER_espeakWORDGAP: espeak_PARAMETER = 7;
+pub const espeak_PARAMETER_espeakOPTIONS: espeak_PARAMETER = 8;
+pub const espeak_PARAMETER_espeakINTONATION: espeak_PARAMETER = 9;
+pub const espeak_PARAMETER_espeakSSML_BREAK_MUL: espeak_PARAMETER = 10;
+pub const espeak_PARAMETER_espeakRESERVED2: espeak_PARAMETER = 11;
+pub const espeak_PARAMETER_espeakEMPHASIS: espeak_PARAMETER = 12;
+pub const espeak_PARAMETER_espeakLINELENGTH: espeak_PARAMETER = 13;
+pub const espeak_PARAMETER_espeakVOICETYPE: espeak_PARAMETER = 14;
+pub const espeak_PARAMETER_N_SPEECH_PARAM: espeak_PARAMETER = 15;
+
+pub type espeak_PUNCT_TYPE = ::std::os::raw::c_uint;
+pub const espeak_PUNCT_TYPE_espeakPUNCT_NONE: espeak_PUNCT_TYPE = 0;
+pub const espeak_PUNCT_TYPE_espeakPUNCT_ALL: espeak_PUNCT_TYPE = 1;
+pub const espeak_PUNCT_TYPE_espeakPUNCT_SOME: espeak_PUNCT_TYPE = 2;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct espeak_VOICE {
+    /// A given name for this voice. UTF8 string
+    pub name: *const ::std::os::raw::c_char,
+    /// List of pairs of (byte) priority + (string) language (and dialect qualifier)
+    pub languages: *const ::std::os::raw::c_char,
+    /// The filename for this voice within espeak-ng-data/voices
+    pub identifier: *const ::std::os::raw::c_char,
+    /// 0=none 1=male, 2=female
+    pub gender: ::std::os::raw::c_uchar,
+    /// 0=not specified, or age in years
+    pub age: ::std::os::raw::c_uchar,
+    /// Only used when passed as a parameter to espeak_SetVoiceByProperties
+    pub variant: ::std::os::raw::c_uchar,
+    /// For internal use
+    pub xx1: ::std::os::raw::c_uchar,
+    /// For internal use
+    pub score: ::std::os::raw::c_int,
+    /// For internal use
+    pub spare: *mut ::std::os::raw::c_void,
+}
+
+extern "C" {
+    pub fn espeak_Initialize(
+        output: espeak_AUDIO_OUTPUT,
+        buflength: ::std::os::raw::c_int,
+        path: *const ::std::os::raw::c_char,
+        options: ::std::os::raw::c_int,
+    ) -> ::std::os::raw::c_int;
+
+    pub fn espeak_SetSynthCallback(SynthCallback: t_espeak_callback);
+
+    pub fn espeak_SetUriCallback(
+        UriCallback: ::std::option::Option<
+            unsafe extern "C" fn(
+                arg1: ::std::os::raw::c_int,
+                arg2: *const ::std::os::raw::c_char,
+                arg3: *const ::std::os::raw::c_char,
+            ) -> ::std::os::raw::c_int,
+        >,
+    );
+
+    pub fn espeak_SetPhonemeCallback(
+        PhonemeCallback: ::std::option::Option<
+            unsafe extern "C" fn(arg1: *const ::std::os::raw::c_char) -> ::std::os::raw::c_int,
+        >,
+    );
+
+    pub fn espeak_Synth(
+        text: *const ::std::os::raw::c_void,
+        size: usize,
+        position: ::std::os::raw::c_uint,
+        position_type: espeak_POSITION_TYPE,
+        end_position: ::std::os::raw::c_uint,
+        flags: ::std::os::raw::c_uint,
+        unique_identifier: *mut ::std::os::raw::c_uint,
+        user_data: *mut ::std::os::raw::c_void,
+    ) -> espeak_ERROR;
+
+    pub fn espeak_Synth_Mark(
+        text: *const ::std::os::raw::c_void,
+        size: usize,
+        index_mark: *const ::std::os::raw::c_char,
+        end_position: ::std::os::raw::c_uint,
+        flags: ::std::os::raw::c_uint,
+        unique_identifier: *mut ::std::os::raw::c_uint,
+        user_data: *mut ::std::os::raw::c_void,
+    ) -> espeak_ERROR;
+
+    pub fn espeak_Key(key_name: *const ::std::os::raw::c_char) -> espeak_ERROR;
+
+    pub fn espeak_Char(character: wchar_t) -> espeak_ERROR;
+
+    pub fn espeak_SetParameter(
+        parameter: espeak_PARAMETER,
+        value: ::std::os::raw::c_int,
+        relative: ::std::os::raw::c_int,
+    ) -> espeak_ERROR;
+
+    pub fn espeak_GetParameter(
+        parameter: espeak_PARAMETER,
+        current: ::std::os::raw::c_int,
+    ) -> ::std::os::raw::c_int;
+
+    pub fn espeak_SetPunctuationList(punctlist: *const wchar_t) -> espeak_ERROR;
+
+    pub fn espeak_SetPhonemeTrace(phonememode: ::std::os::raw::c_int, stream: *mut FILE);
+
+    pub fn espeak_TextToPhonemes(
+        textptr: *mut *const ::std::os::raw::c_void,
+        textmode: ::std::os::raw::c_int,
+        phonememode: ::std::os::raw::c_int,
+    ) -> *const ::std::os::raw::c_char;
+
+    pub fn espeak_TextToPhonemesWithTerminator(
+        textptr: *mut *const ::std::os::raw::c_void,
+        textmode: ::std::os::raw::c_int,
+        phonememode: ::std::os::raw::c_int,
+        terminator: *mut ::std::os::raw::c_int,
+    ) -> *const ::std::os::raw::c_char;
+
+    pub fn espeak_CompileDictionary(
+        path: *const ::std::os::raw::c_char,
+        log: *mut FILE,
+        flags: ::std::os::raw::c_int,
+    );
+
+    pub fn espeak_ListVoices(voice_spec: *mut espeak_VOICE) -> *mut *const espeak_VOICE;
+
+    pub fn espeak_SetVoiceByFile(filename: *const ::std::os::raw::c_char) -> espeak_ERROR;
+
+    pub fn espeak_SetVoiceByName(name: *const ::std::os::raw::c_char) -> espeak_ERROR;
+
+    pub fn espeak_SetVoiceByProperties(voice_spec: *mut espeak_VOICE) -> espeak_ERROR;
+
+    pub fn espeak_GetCurrentVoice() -> *mut espeak_VOICE;
+
+    pub fn espeak_Cancel() -> espeak_ERROR;
+
+    pub fn espeak_IsPlaying() -> ::std::os::raw::c_int;
+
+    pub fn espeak_Synchronize() -> espeak_ERROR;
+
+    pub fn espeak_Terminate() -> espeak_ERROR;
+
+    pub fn espeak_Info(
+        path_data: *mut *const ::std::os::raw::c_char,
+    ) -> *const ::std::os::raw::c_char;
+}
@@ -0,0 +1,322 @@
+use std::collections::HashMap;
+
+use once_cell::sync::Lazy;
+
+//----------------------------------------------------------------
+
+/// Number of most frequent trigrams kept per profile, as in Cavnar & Trenkle's n-gram text categorization
+const PROFILE_SIZE: usize = 300;
+
+/// Texts with fewer letters than this are too short to identify
+const MIN_LETTERS: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Script {
+    Latin,
+    Cyrillic,
+    Greek,
+    Arabic,
+    Hebrew,
+    Devanagari,
+    Thai,
+    Georgian,
+    Armenian,
+    Hangul,
+    Kana,
+    Han,
+}
+
+impl Script {
+    fn of(c: char) -> Option<Self> {
+        let script = match c as u32 {
+            0x0041..=0x005A | 0x0061..=0x007A | 0x00C0..=0x024F | 0x1E00..=0x1EFF => Self::Latin,
+            0x0400..=0x052F => Self::Cyrillic,
+            0x0370..=0x03FF | 0x1F00..=0x1FFF => Self::Greek,
+            0x0600..=0x06FF | 0x0750..=0x077F | 0xFB50..=0xFDFF | 0xFE70..=0xFEFF => Self::Arabic,
+            0x0590..=0x05FF => Self::Hebrew,
+            0x0900..=0x097F => Self::Devanagari,
+            0x0E00..=0x0E7F => Self::Thai,
+            0x10A0..=0x10FF => Self::Georgian,
+            0x0530..=0x058F => Self::Armenian,
+            0x1100..=0x11FF | 0x3130..=0x318F | 0xAC00..=0xD7AF => Self::Hangul,
+            0x3040..=0x30FF => Self::Kana,
+            0x4E00..=0x9FFF | 0x3400..=0x4DBF => Self::Han,
+            _ => return None,
+        };
+        Some(script)
+    }
+    /// Language of scripts that are written by a single language supported by eSpeak-ng
+    fn single_language(&self) -> Option<&'static str> {
+        match self {
+            Self::Greek => Some("el"),
+            Self::Hebrew => Some("he"),
+            Self::Devanagari => Some("hi"),
+            Self::Thai => Some("th"),
+            Self::Georgian => Some("ka"),
+            Self::Armenian => Some("hy"),
+            Self::Hangul => Some("ko"),
+            Self::Kana => Some("ja"),
+            Self::Han => Some("zh"),
+            _ => None,
+        }
+    }
+}
+
+/// Sample text the trigram profile of each language is built from, with the script it is written in
+const SAMPLES: [(&str, Script, &str); 17] = [
+    ("en", Script::Latin, "All human beings are born free and equal in dignity and rights. They are endowed with reason and conscience and should act towards one another in a spirit of brotherhood. I think that we should go home now because it is getting late and the weather is getting worse. The children are playing in the garden with their friends and the dog is sleeping under the table. What time is it? Where have you been all this time?"),
+    ("de", Script::Latin, "Alle Menschen sind frei und gleich an Würde und Rechten geboren. Sie sind mit Vernunft und Gewissen begabt und sollen einander im Geist der Brüderlichkeit begegnen. Ich glaube, dass wir jetzt nach Hause gehen sollten, weil es schon spät ist und das Wetter schlechter wird. Die Kinder spielen im Garten mit ihren Freunden und der Hund schläft unter dem Tisch. Wie spät ist es? Wo bist du die ganze Zeit gewesen?"),
+    ("fr", Script::Latin, "Tous les êtres humains naissent libres et égaux en dignité et en droits. Ils sont doués de raison et de conscience et doivent agir les uns envers les autres dans un esprit de fraternité. Je pense que nous devrions rentrer à la maison maintenant parce qu'il est tard et que le temps devient mauvais. Les enfants jouent dans le jardin avec leurs amis et le chien dort sous la table. Quelle heure est-il? Où étais-tu pendant tout ce temps?"),
+    ("es", Script::Latin, "Todos los seres humanos nacen libres e iguales en dignidad y derechos y, dotados como están de razón y conciencia, deben comportarse fraternalmente los unos con los otros. Creo que deberíamos volver a casa ahora porque ya es tarde y el tiempo se está poniendo feo. Los niños juegan en el jardín con sus amigos y el perro duerme debajo de la mesa. ¿Qué hora es? ¿Dónde has estado todo este tiempo?"),
+    ("it", Script::Latin, "Tutti gli esseri umani nascono liberi ed eguali in dignità e diritti. Essi sono dotati di ragione e di coscienza e devono agire gli uni verso gli altri in spirito di fratellanza. Penso che dovremmo tornare a casa adesso perché è già tardi e il tempo sta peggiorando. I bambini giocano nel giardino con i loro amici e il cane dorme sotto il tavolo. Che ore sono? Dove sei stato per tutto questo tempo?"),
+    ("pt", Script::Latin, "Todos os seres humanos nascem livres e iguais em dignidade e em direitos. Dotados de razão e de consciência, devem agir uns para com os outros em espírito de fraternidade. Acho que devíamos voltar para casa agora porque já é tarde e o tempo está a ficar pior. As crianças brincam no jardim com os seus amigos e o cão dorme debaixo da mesa. Que horas são? Onde estiveste durante todo este tempo?"),
+    ("nl", Script::Latin, "Alle mensen worden vrij en gelijk in waardigheid en rechten geboren. Zij zijn begiftigd met verstand en geweten, en behoren zich jegens elkander in een geest van broederschap te gedragen. Ik denk dat we nu naar huis moeten gaan, omdat het al laat is en het weer slechter wordt. De kinderen spelen in de tuin met hun vrienden en de hond slaapt onder de tafel. Hoe laat is het? Waar ben je al die tijd geweest?"),
+    ("pl", Script::Latin, "Wszyscy ludzie rodzą się wolni i równi pod względem swej godności i swych praw. Są oni obdarzeni rozumem i sumieniem i powinni postępować wobec innych w duchu braterstwa. Myślę, że powinniśmy teraz wrócić do domu, ponieważ jest już późno i pogoda się psuje. Dzieci bawią się w ogrodzie ze swoimi przyjaciółmi, a pies śpi pod stołem. Która jest godzina? Gdzie byłeś przez cały ten czas?"),
+    ("cs", Script::Latin, "Všichni lidé rodí se svobodní a sobě rovní co do důstojnosti a práv. Jsou nadáni rozumem a svědomím a mají spolu jednat v duchu bratrství. Myslím, že bychom teď měli jít domů, protože už je pozdě a počasí se zhoršuje. Děti si hrají na zahradě se svými kamarády a pes spí pod stolem. Kolik je hodin? Kde jsi byl celou tu dobu?"),
+    ("sv", Script::Latin, "Alla människor är födda fria och lika i värde och rättigheter. De har utrustats med förnuft och samvete och bör handla gentemot varandra i en anda av broderskap. Jag tror att vi borde gå hem nu eftersom det redan är sent och vädret blir sämre. Barnen leker i trädgården med sina vänner och hunden sover under bordet. Vad är klockan? Var har du varit hela den här tiden?"),
+    ("fi", Script::Latin, "Kaikki ihmiset syntyvät vapaina ja tasavertaisina arvoltaan ja oikeuksiltaan. Heille on annettu järki ja omatunto, ja heidän on toimittava toisiaan kohtaan veljeyden hengessä. Luulen, että meidän pitäisi mennä nyt kotiin, koska on jo myöhä ja sää huononee. Lapset leikkivät puutarhassa ystäviensä kanssa ja koira nukkuu pöydän alla. Paljonko kello on? Missä olet ollut koko tämän ajan?"),
+    ("tr", Script::Latin, "Bütün insanlar hür, haysiyet ve haklar bakımından eşit doğarlar. Akıl ve vicdana sahiptirler ve birbirlerine karşı kardeşlik zihniyeti ile hareket etmelidirler. Bence artık eve gitmeliyiz çünkü saat geç oldu ve hava kötüleşiyor. Çocuklar bahçede arkadaşlarıyla oynuyor ve köpek masanın altında uyuyor. Saat kaç? Bunca zamandır neredeydin?"),
+    ("ru", Script::Cyrillic, "Все люди рождаются свободными и равными в своем достоинстве и правах. Они наделены разумом и совестью и должны поступать в отношении друг друга в духе братства. Я думаю, что нам пора идти домой, потому что уже поздно и погода портится. Дети играют в саду со своими друзьями, а собака спит под столом. Который час? Где ты был всё это время?"),
+    ("uk", Script::Cyrillic, "Всі люди народжуються вільними і рівними у своїй гідності та правах. Вони наділені розумом і совістю і повинні діяти у відношенні один до одного в дусі братерства. Я думаю, що нам час іти додому, тому що вже пізно і погода псується. Діти граються в саду зі своїми друзями, а собака спить під столом. Котра година? Де ти був весь цей час?"),
+    ("bg", Script::Cyrillic, "Всички хора се раждат свободни и равни по достойнство и права. Те са надарени с разум и съвест и следва да се отнасят помежду си в дух на братство. Мисля, че трябва да се прибираме вкъщи, защото вече е късно и времето се разваля. Децата играят в градината с приятелите си, а кучето спи под масата. Колко е часът? Къде беше през цялото това време?"),
+    ("ar", Script::Arabic, "يولد جميع الناس أحرارًا متساوين في الكرامة والحقوق. وقد وهبوا عقلًا وضميرًا وعليهم أن يعامل بعضهم بعضًا بروح الإخاء. أعتقد أنه يجب أن نعود إلى البيت الآن لأن الوقت متأخر والطقس يسوء. الأطفال يلعبون في الحديقة مع أصدقائهم والكلب نائم تحت الطاولة. كم الساعة؟ أين كنت طوال هذا الوقت؟"),
+    ("fa", Script::Arabic, "تمام افراد بشر آزاد به دنیا می‌آیند و از لحاظ حیثیت و حقوق با هم برابرند. همه دارای عقل و وجدان هستند و باید نسبت به یکدیگر با روح برادری رفتار کنند. فکر می‌کنم باید الان به خانه برگردیم چون دیر شده و هوا بد می‌شود. بچه‌ها با دوستانشان در باغ بازی می‌کنند و سگ زیر میز خوابیده است. ساعت چند است؟ این همه مدت کجا بودی؟"),
+];
+
+/// Trigram profiles of the built-in samples, built on first use
+static BUILTIN_PROFILES: Lazy<Vec<LanguageProfile>> = Lazy::new(|| {
+    SAMPLES
+        .iter()
+        .map(|(language, script, sample)| LanguageProfile {
+            language,
+            script: *script,
+            ranks: trigram_ranks(sample),
+        })
+        .collect()
+});
+
+struct LanguageProfile {
+    language: &'static str,
+    script: Script,
+    ranks: HashMap<String, usize>,
+}
+
+/// Result of language identification
+#[derive(Debug, Clone, PartialEq)]
+pub struct DetectedLanguage {
+    /// ISO 639-1 code, which eSpeak-ng accepts as a voice name
+    pub language: &'static str,
+    /// Between 0 and 1, how clearly the best match stands out from the runner-up
+    pub confidence: f32,
+}
+
+/// Offline language identification based on the script of the text and character trigram statistics
+///
+/// The script decides languages that are the only ones written in it (Greek, Korean, ...),
+/// trigram profiles built from embedded samples choose among languages that share a script.
+#[derive(Debug, Clone, Default)]
+pub struct LanguageDetector {
+    /// Languages to choose from, all built-in languages if empty
+    languages: Vec<String>,
+}
+
+impl LanguageDetector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Restricts detection to the given languages, e.g. the ones there are voices for
+    pub fn with_languages(languages: &[&str]) -> Self {
+        Self {
+            languages: languages.iter().map(|l| l.to_lowercase()).collect(),
+        }
+    }
+    /// Languages the detector can recognize
+    pub fn supported_languages() -> Vec<&'static str> {
+        let script_languages = [
+            Script::Greek,
+            Script::Hebrew,
+            Script::Devanagari,
+            Script::Thai,
+            Script::Georgian,
+            Script::Armenian,
+            Script::Hangul,
+            Script::Kana,
+            Script::Han,
+        ]
+        .iter()
+        .filter_map(Script::single_language);
+        SAMPLES
+            .iter()
+            .map(|(language, _, _)| *language)
+            .chain(script_languages)
+            .collect()
+    }
+    fn is_candidate(&self, language: &str) -> bool {
+        self.languages.is_empty() || self.languages.iter().any(|l| l == language)
+    }
+    pub fn detect(&self, text: &str) -> Option<DetectedLanguage> {
+        let mut script_counts: HashMap<Script, usize> = HashMap::new();
+        for script in text.chars().filter_map(Script::of) {
+            *script_counts.entry(script).or_default() += 1;
+        }
+        // Japanese mixes kana with Han characters
+        if script_counts.contains_key(&Script::Kana) {
+            let han = script_counts.remove(&Script::Han).unwrap_or(0);
+            *script_counts.entry(Script::Kana).or_default() += han;
+        }
+        let (script, letters) = script_counts.into_iter().max_by_key(|(_, count)| *count)?;
+        if letters < MIN_LETTERS {
+            return None;
+        }
+        if let Some(language) = script.single_language() {
+            return self.is_candidate(language).then_some(DetectedLanguage {
+                language,
+                confidence: 1.0,
+            });
+        }
+
+        let ranks = trigram_ranks(text);
+        let mut distances = Vec::from_iter(
+            BUILTIN_PROFILES
+                .iter()
+                .filter(|profile| profile.script == script && self.is_candidate(profile.language))
+                .map(|profile| (profile.language, profile_distance(&ranks, &profile.ranks))),
+        );
+        distances.sort_by(|a, b| a.1.total_cmp(&b.1));
+        let (language, best) = *distances.first()?;
+        let confidence = match distances.get(1) {
+            Some((_, second)) if *second > 0.0 => (second - best) / second,
+            _ => 1.0,
+        };
+        Some(DetectedLanguage {
+            language,
+            confidence,
+        })
+    }
+}
+
+/// Ranks of the most frequent character trigrams of the lowercased words in `text`, padded with spaces
+fn trigram_ranks(text: &str) -> HashMap<String, usize> {
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    let lowercase = text.to_lowercase();
+    for word in lowercase.split(|c: char| !c.is_alphabetic()) {
+        if word.is_empty() {
+            continue;
+        }
+        let padded = Vec::from_iter(std::iter::once(' ').chain(word.chars()).chain([' ']));
+        for trigram in padded.windows(3) {
+            *counts.entry(String::from_iter(trigram)).or_default() += 1;
+        }
+    }
+    let mut trigrams = Vec::from_iter(counts);
+    // Ties are broken alphabetically so that profiles do not depend on hash order
+    trigrams.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    trigrams
+        .into_iter()
+        .take(PROFILE_SIZE)
+        .enumerate()
+        .map(|(rank, (trigram, _))| (trigram, rank))
+        .collect()
+}
+
+/// Average out-of-place distance between the trigram ranks of a text and a language profile
+fn profile_distance(text: &HashMap<String, usize>, profile: &HashMap<String, usize>) -> f32 {
+    if text.is_empty() {
+        return PROFILE_SIZE as f32;
+    }
+    let total: usize = text
+        .iter()
+        .map(|(trigram, rank)| match profile.get(trigram) {
+            Some(profile_rank) => rank.abs_diff(*profile_rank),
+            None => PROFILE_SIZE,
+        })
+        .sum();
+    total as f32 / text.len() as f32
+}
+
+/// Splits text into paragraphs separated by blank lines
+pub fn split_paragraphs(text: &str) -> Vec<&str> {
+    let mut paragraphs = Vec::new();
+    let mut start = 0;
+    let mut offset = 0;
+    let mut previous_blank = false;
+    for line in text.split_inclusive('\n') {
+        let blank = line.trim().is_empty();
+        if blank && !previous_blank && offset > start {
+            paragraphs.push(&text[start..offset]);
+        }
+        offset += line.len();
+        if blank {
+            start = offset;
+        }
+        previous_blank = blank;
+    }
+    if offset > start {
+        paragraphs.push(&text[start..offset]);
+    }
+    paragraphs.retain(|paragraph| !paragraph.trim().is_empty());
+    paragraphs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn detect(text: &str) -> Option<&'static str> {
+        LanguageDetector::new().detect(text).map(|d| d.language)
+    }
+
+    #[test]
+    fn test_detects_by_trigrams() {
+        assert_eq!(
+            detect("The weather is nice today and we are going to the beach."),
+            Some("en")
+        );
+        assert_eq!(
+            detect("Heute ist das Wetter schön und wir gehen an den Strand."),
+            Some("de")
+        );
+        assert_eq!(
+            detect("Il fait beau aujourd'hui et nous allons à la plage."),
+            Some("fr")
+        );
+        assert_eq!(
+            detect("Hoy hace buen tiempo y vamos a la playa con los niños."),
+            Some("es")
+        );
+        assert_eq!(
+            detect("Сегодня хорошая погода, и мы идём на пляж."),
+            Some("ru")
+        );
+        assert_eq!(
+            detect("Сьогодні гарна погода, і ми йдемо на пляж."),
+            Some("uk")
+        );
+    }
+
+    #[test]
+    fn test_detects_by_script() {
+        assert_eq!(detect("Καλημέρα σας"), Some("el"));
+        assert_eq!(detect("안녕하세요"), Some("ko"));
+        assert_eq!(detect("今日は天気がいいですね"), Some("ja"));
+        assert_eq!(detect("今天天气很好"), Some("zh"));
+        assert_eq!(detect("12:00 !"), None);
+    }
+
+    #[test]
+    fn test_restricted_languages() {
+        let detector = LanguageDetector::with_languages(&["en", "ru"]);
+        let detected = detector.detect("Heute ist das Wetter schön.").unwrap();
+        assert!(["en", "ru"].contains(&detected.language));
+        assert_eq!(detector.detect("Καλημέρα σας"), None);
+    }
+
+    #[test]
+    fn test_split_paragraphs() {
+        let text = "First line\nsecond line\n\n  \nSecond paragraph\n\nThird";
+        assert_eq!(
+            split_paragraphs(text),
+            ["First line\nsecond line\n", "Second paragraph\n", "Third"]
+        );
+    }
+}
@@ -0,0 +1,481 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::SystemTime;
+
+use serde::Deserialize;
+
+use crate::core::{Phonemes, PiperError, PiperResult, TextSpan};
+use crate::phonemizer::{Phonemizer, Punctuation};
+
+//----------------------------------------------------------------
+
+const SENTENCE_TERMINATORS: [char; 3] = ['.', '?', '!'];
+
+/// On-disk format of a lexicon file
+///
+/// ```json
+/// {
+///     "case_sensitive": false,
+///     "languages": {
+///         "en-us": { "piper": "pˈaɪpɚ" }
+///     }
+/// }
+/// ```
+#[derive(Deserialize)]
+struct LexiconFile {
+    #[serde(default)]
+    case_sensitive: bool,
+    languages: HashMap<String, HashMap<String, String>>,
+}
+
+/// Pronunciation overrides mapping words to phonemes, per language
+#[derive(Debug, Clone, Default)]
+pub struct Lexicon {
+    case_sensitive: bool,
+    entries: HashMap<String, HashMap<String, String>>,
+}
+
+impl Lexicon {
+    pub fn new(case_sensitive: bool) -> Self {
+        Self {
+            case_sensitive,
+            entries: HashMap::new(),
+        }
+    }
+    pub fn from_file(path: &Path) -> PiperResult<Self> {
+        let file = File::open(path).map_err(|e| PiperError::Io {
+            message: format!("Failed to open lexicon: `{}`", path.display()),
+            source: Some(e),
+        })?;
+        let lexicon_file: LexiconFile =
+            serde_json::from_reader(file).map_err(|e| PiperError::InvalidConfig {
+                message: format!("Failed to parse lexicon from file: `{}`", path.display()),
+                source: Some(e),
+            })?;
+        let mut lexicon = Self::new(lexicon_file.case_sensitive);
+        for (language, words) in lexicon_file.languages {
+            for (word, phonemes) in words {
+                lexicon.insert(&language, &word, phonemes);
+            }
+        }
+        Ok(lexicon)
+    }
+    pub fn insert(&mut self, language: &str, word: &str, phonemes: String) {
+        let word = self.normalize_word(word);
+        self.entries
+            .entry(language.to_lowercase())
+            .or_default()
+            .insert(word, phonemes);
+    }
+    /// Looks up a word for the given language, falling back to the base language (`en` for `en-us`)
+    pub fn lookup(&self, word: &str, language: &str) -> Option<&str> {
+        let word = self.normalize_word(word);
+        let language = language.to_lowercase();
+        let base_language = language.split(['-', '_']).next().unwrap_or_default();
+        let phonemes = [language.as_str(), base_language]
+            .into_iter()
+            .find_map(|lang| self.entries.get(lang)?.get(&word))?;
+        Some(phonemes.as_str())
+    }
+    pub fn is_empty(&self) -> bool {
+        self.entries.values().all(|words| words.is_empty())
+    }
+    fn normalize_word(&self, word: &str) -> String {
+        if self.case_sensitive {
+            word.to_string()
+        } else {
+            word.to_lowercase()
+        }
+    }
+}
+
+/// Phonemizer that uses lexicon entries for matching words and hands the rest of the text to another phonemizer
+///
+/// When created from a file with auto reload enabled, the file is re-read whenever its modification time changes,
+/// so long running services pick up lexicon edits without a restart.
+pub struct LexiconPhonemizer {
+    lexicon: RwLock<Lexicon>,
+    inner: Arc<dyn Phonemizer>,
+    punctuation: Punctuation,
+    path: Option<PathBuf>,
+    modified: Mutex<Option<SystemTime>>,
+    auto_reload: bool,
+}
+
+impl LexiconPhonemizer {
+    pub fn new(lexicon: Lexicon, inner: Arc<dyn Phonemizer>) -> Self {
+        Self {
+            lexicon: RwLock::new(lexicon),
+            inner,
+            punctuation: Punctuation::default(),
+            path: None,
+            modified: Mutex::new(None),
+            auto_reload: false,
+        }
+    }
+    pub fn from_file(path: PathBuf, inner: Arc<dyn Phonemizer>) -> PiperResult<Self> {
+        let modified = modification_time(&path);
+        let lexicon = Lexicon::from_file(&path)?;
+        Ok(Self {
+            lexicon: RwLock::new(lexicon),
+            inner,
+            punctuation: Punctuation::default(),
+            path: Some(path),
+            modified: Mutex::new(modified),
+            auto_reload: true,
+        })
+    }
+    /// The punctuation the inner phonemizer keeps, see [`PhonemeOptions::punctuation`](crate::phonemizer::PhonemeOptions::punctuation),
+    /// punctuation next to lexicon words is kept alike
+    pub fn with_punctuation(mut self, punctuation: Punctuation) -> Self {
+        self.punctuation = punctuation;
+        self
+    }
+    pub fn with_auto_reload(mut self, auto_reload: bool) -> Self {
+        self.auto_reload = auto_reload;
+        self
+    }
+    /// Re-reads the lexicon file, keeping the current entries if it can not be loaded
+    pub fn reload(&self) -> PiperResult<()> {
+        let Some(ref path) = self.path else {
+            return Ok(());
+        };
+        let modified = modification_time(path);
+        let lexicon = Lexicon::from_file(path)?;
+        *self.write_lexicon()? = lexicon;
+        *self.lock_modified()? = modified;
+        Ok(())
+    }
+    pub fn set_lexicon(&self, lexicon: Lexicon) -> PiperResult<()> {
+        *self.write_lexicon()? = lexicon;
+        Ok(())
+    }
+    fn reload_if_changed(&self) -> PiperResult<()> {
+        let Some(ref path) = self.path else {
+            return Ok(());
+        };
+        let modified = modification_time(path);
+        if modified.is_none() || modified == *self.lock_modified()? {
+            return Ok(());
+        }
+        // A half written or invalid file must not break synthesis, keep using the current entries
+        // until the file changes again
+        if self.reload().is_err() {
+            *self.lock_modified()? = modified;
+        }
+        Ok(())
+    }
+    /// Phonemizes text between lexicon words, continuing the sentence that is being built
+    ///
+    /// `chunk_start` is the byte offset of the chunk in the phonemized text.
+    fn phonemize_chunk(
+        &self,
+        chunk: &str,
+        chunk_start: usize,
+        language: &str,
+        sentences: &mut SentenceBuilder,
+    ) -> PiperResult<()> {
+        // Punctuation directly following a lexicon word belongs to it, keep what eSpeak-ng would have kept
+        let symbols = self.punctuation.symbols();
+        let text_start = chunk.find(char::is_alphanumeric).unwrap_or(chunk.len());
+        for (i, c) in chunk[..text_start]
+            .char_indices()
+            .filter(|(_, c)| symbols.contains(*c))
+        {
+            let start = chunk_start + i;
+            sentences.push_punctuation(c, start..start + c.len_utf8());
+        }
+        let chunk = &chunk[text_start..];
+        let chunk_start = chunk_start + text_start;
+        let trimmed = chunk.trim_end();
+        if trimmed.is_empty() {
+            return Ok(());
+        }
+        let ends_clause = trimmed.ends_with(|c| symbols.contains(c));
+        let mut chunk_sentences = Vec::from_iter(self.inner.phonemize(chunk, language)?);
+        if !ends_clause {
+            // eSpeak-ng terminates its input with a full stop, but the sentence continues after the chunk
+            if let Some((last, _)) = chunk_sentences.last_mut() {
+                if last.ends_with('.') {
+                    last.pop();
+                }
+            }
+        }
+        let num_sentences = chunk_sentences.len();
+        for (i, (sentence, source)) in chunk_sentences.into_iter().enumerate() {
+            if i > 0 {
+                sentences.end_sentence();
+            }
+            // Phonemizers that don't track sources are attributed the whole chunk
+            let span = source.map_or(0..trimmed.len(), |source| source.bytes);
+            sentences.append(&sentence, chunk_start + span.start..chunk_start + span.end);
+            if i + 1 == num_sentences && trimmed.ends_with(SENTENCE_TERMINATORS) {
+                sentences.end_sentence();
+            }
+        }
+        Ok(())
+    }
+    fn write_lexicon(&self) -> PiperResult<std::sync::RwLockWriteGuard<'_, Lexicon>> {
+        self.lexicon
+            .write()
+            .map_err(|_| PiperError::OperationError("Lexicon lock is poisoned".to_string()))
+    }
+    fn lock_modified(&self) -> PiperResult<std::sync::MutexGuard<'_, Option<SystemTime>>> {
+        self.modified
+            .lock()
+            .map_err(|_| PiperError::OperationError("Lexicon lock is poisoned".to_string()))
+    }
+}
+
+impl Phonemizer for LexiconPhonemizer {
+    fn phonemize(&self, text: &str, language: &str) -> PiperResult<Phonemes> {
+        if self.auto_reload {
+            self.reload_if_changed()?;
+        }
+        let lexicon = self
+            .lexicon
+            .read()
+            .map_err(|_| PiperError::OperationError("Lexicon lock is poisoned".to_string()))?;
+        if lexicon.is_empty() {
+            return self.inner.phonemize(text, language);
+        }
+
+        let mut sentences = SentenceBuilder::default();
+        let mut pending_start = 0;
+        for (start, end) in word_spans(text) {
+            if let Some(phonemes) = lexicon.lookup(&text[start..end], language) {
+                self.phonemize_chunk(
+                    &text[pending_start..start],
+                    pending_start,
+                    language,
+                    &mut sentences,
+                )?;
+                sentences.append(phonemes, start..end);
+                pending_start = end;
+            }
+        }
+        if pending_start == 0 {
+            return self.inner.phonemize(text, language);
+        }
+        self.phonemize_chunk(
+            &text[pending_start..],
+            pending_start,
+            language,
+            &mut sentences,
+        )?;
+        Ok(sentences.finish(text, self.punctuation.symbols()))
+    }
+}
+
+/// Collects sentences along with the byte range of the text they were produced from
+#[derive(Default)]
+struct SentenceBuilder {
+    sentences: Vec<(String, Range<usize>)>,
+    current: String,
+    current_span: Option<Range<usize>>,
+}
+
+impl SentenceBuilder {
+    fn append(&mut self, phonemes: &str, span: Range<usize>) {
+        if phonemes.is_empty() {
+            return;
+        }
+        if !self.current.is_empty() && !self.current.ends_with(char::is_whitespace) {
+            self.current.push(' ');
+        }
+        self.current.push_str(phonemes);
+        self.extend_span(span);
+    }
+    fn push_punctuation(&mut self, c: char, span: Range<usize>) {
+        self.current.push(c);
+        self.extend_span(span);
+        if SENTENCE_TERMINATORS.contains(&c) {
+            self.end_sentence();
+        }
+    }
+    fn extend_span(&mut self, span: Range<usize>) {
+        self.current_span = Some(match self.current_span.take() {
+            Some(current) => current.start.min(span.start)..current.end.max(span.end),
+            None => span,
+        });
+    }
+    fn end_sentence(&mut self) {
+        let sentence = std::mem::take(&mut self.current);
+        let span = self.current_span.take();
+        if let (false, Some(span)) = (sentence.trim().is_empty(), span) {
+            self.sentences.push((sentence.trim().to_string(), span));
+        }
+    }
+    /// `symbols` is the punctuation that can end a sentence's phonemes, others get a full stop
+    fn finish(mut self, text: &str, symbols: &str) -> Phonemes {
+        let current = self.current.trim_end();
+        if !current.is_empty() && !current.ends_with(|c| symbols.contains(c)) {
+            self.current.push('.');
+        }
+        self.end_sentence();
+        Phonemes::from_iter(
+            self.sentences
+                .into_iter()
+                .map(|(sentence, span)| (sentence, Some(TextSpan::new(text, span)))),
+        )
+    }
+}
+
+/// Byte ranges of the words in `text`, words may contain inner apostrophes and hyphens
+fn word_spans(text: &str) -> Vec<(usize, usize)> {
+    let mut spans = Vec::new();
+    let mut start: Option<usize> = None;
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        let next_is_alphanumeric = chars.peek().is_some_and(|(_, n)| n.is_alphanumeric());
+        let in_word = c.is_alphanumeric()
+            || (start.is_some() && matches!(c, '\'' | '’' | '-') && next_is_alphanumeric);
+        match (in_word, start) {
+            (true, None) => start = Some(i),
+            (false, Some(s)) => {
+                spans.push((s, i));
+                start = None;
+            }
+            _ => (),
+        }
+    }
+    if let Some(s) = start {
+        spans.push((s, text.len()));
+    }
+    spans
+}
+
+fn modification_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Stands in for eSpeak-ng: upper cases words and ends its input with a full stop
+    struct UppercasePhonemizer;
+
+    const CLAUSE_BREAKERS: [char; 6] = ['.', ',', '?', '!', ';', ':'];
+
+    impl Phonemizer for UppercasePhonemizer {
+        fn phonemize(&self, text: &str, _language: &str) -> PiperResult<Phonemes> {
+            let mut sentences = Phonemes::new();
+            let mut start = 0;
+            for sentence in text.split_inclusive(['.', '?', '!']) {
+                let end = start + sentence.len();
+                let trimmed = sentence.trim();
+                if !trimmed.is_empty() {
+                    let mut phonemes = trimmed.to_uppercase();
+                    if !phonemes.ends_with(CLAUSE_BREAKERS) {
+                        phonemes.push('.');
+                    }
+                    let offset = start + sentence.find(trimmed).unwrap_or(0);
+                    let span = TextSpan::new(text, offset..offset + trimmed.len());
+                    sentences.push(phonemes, Some(span));
+                }
+                start = end;
+            }
+            Ok(sentences)
+        }
+    }
+
+    fn phonemizer() -> LexiconPhonemizer {
+        let mut lexicon = Lexicon::new(false);
+        lexicon.insert("en", "Piper", "pˈaɪpɚ".to_string());
+        LexiconPhonemizer::new(lexicon, Arc::new(UppercasePhonemizer))
+    }
+
+    #[test]
+    fn test_lexicon_word_replaced() -> PiperResult<()> {
+        let text = "Say piper twice. Then stop";
+        let phonemes = phonemizer().phonemize(text, "en-us")?;
+        let sources = Vec::from_iter(phonemes.sources().iter().flatten().map(|s| &s.text));
+        assert_eq!(sources, ["Say piper twice.", "Then stop"]);
+        assert_eq!(phonemes.to_vec(), ["SAY pˈaɪpɚ TWICE.", "THEN STOP."]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_lexicon_word_at_sentence_end() -> PiperResult<()> {
+        let phonemes = phonemizer().phonemize("Hello, Piper! Bye", "en-us")?;
+        assert_eq!(phonemes.to_vec(), ["HELLO, pˈaɪpɚ!", "BYE."]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_configured_punctuation_is_kept() -> PiperResult<()> {
+        let text = "Piper; hello: Piper";
+        let phonemes = phonemizer().phonemize(text, "en-us")?;
+        assert_eq!(phonemes.to_vec(), ["pˈaɪpɚ HELLO: pˈaɪpɚ."]);
+        let phonemizer = phonemizer().with_punctuation(Punctuation::Preserve(";:".to_string()));
+        let phonemes = phonemizer.phonemize(text, "en-us")?;
+        assert_eq!(phonemes.to_vec(), ["pˈaɪpɚ; HELLO: pˈaɪpɚ."]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_reloads_changed_file() -> PiperResult<()> {
+        let path = crate::test_util::temp_path("lexicon").with_extension("json");
+        let write_lexicon = |phonemes: &str, modified: SystemTime| {
+            let json = format!(r#"{{"languages": {{"en": {{"piper": "{}"}}}}}}"#, phonemes);
+            std::fs::write(&path, json).unwrap();
+            // Bumped explicitly, file systems with coarse timestamps could otherwise miss the change
+            File::options()
+                .write(true)
+                .open(&path)
+                .and_then(|file| file.set_modified(modified))
+                .unwrap();
+        };
+        let start = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_000_000);
+        write_lexicon("pˈaɪpɚ", start);
+        let phonemizer = LexiconPhonemizer::from_file(path.clone(), Arc::new(UppercasePhonemizer))?;
+        let speak = || {
+            phonemizer
+                .phonemize("Hello piper.", "en-us")
+                .map(|p| p.to_vec())
+        };
+        assert_eq!(speak()?, ["HELLO pˈaɪpɚ."]);
+
+        write_lexicon("pˈiːpɚ", start + std::time::Duration::from_secs(10));
+        assert_eq!(speak()?, ["HELLO pˈiːpɚ."]);
+
+        // Invalid files keep the current entries until the file changes again
+        std::fs::write(&path, "{").unwrap();
+        assert_eq!(speak()?, ["HELLO pˈiːpɚ."]);
+        assert!(phonemizer.reload().is_err());
+
+        // Without auto reload only an explicit reload picks up changes
+        let phonemizer = phonemizer.with_auto_reload(false);
+        write_lexicon("pˈaɪp", start + std::time::Duration::from_secs(20));
+        let speak = || {
+            phonemizer
+                .phonemize("Hello piper.", "en-us")
+                .map(|p| p.to_vec())
+        };
+        assert_eq!(speak()?, ["HELLO pˈiːpɚ."]);
+        phonemizer.reload()?;
+        assert_eq!(speak()?, ["HELLO pˈaɪp."]);
+        std::fs::remove_file(&path).ok();
+        Ok(())
+    }
+
+    #[test]
+    fn test_case_sensitive_lexicon() {
+        let mut lexicon = Lexicon::new(true);
+        lexicon.insert("en-us", "Piper", "pˈaɪpɚ".to_string());
+        assert_eq!(lexicon.lookup("Piper", "en-US"), Some("pˈaɪpɚ"));
+        assert_eq!(lexicon.lookup("piper", "en-US"), None);
+        assert_eq!(lexicon.lookup("Piper", "de"), None);
+    }
+
+    #[test]
+    fn test_word_spans() {
+        let text = "Don't re-use Wi-Fi -- ok";
+        let words = Vec::from_iter(word_spans(text).into_iter().map(|(s, e)| &text[s..e]));
+        assert_eq!(words, ["Don't", "re-use", "Wi-Fi", "ok"]);
+    }
+}
@@ -1,9 +1,21 @@
 // #![feature(let_chains)]
 
+#[cfg(feature = "bundled-data")]
+mod bundled;
 mod espeakng;
+mod lru;
 mod phonemize;
+#[cfg(test)]
+mod test_util;
 mod wave_writer;
 
+pub mod audio_cache;
 pub mod core;
+#[cfg(feature = "langid")]
+pub mod langid;
+pub mod lexicon;
+pub mod normalize;
+pub mod phonemizer;
+pub mod ssml;
 pub mod synth;
 pub mod vits;
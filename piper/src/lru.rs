@@ -0,0 +1,93 @@
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+use std::sync::{Mutex, MutexGuard};
+
+/// Map that holds at most `capacity` entries, evicting the least recently used one when full
+pub(crate) struct LruCache<K, V> {
+    capacity: usize,
+    entries: HashMap<K, (V, u64)>,
+    /// Keys by the tick of their last use, the first one is evicted next
+    recency: BTreeMap<u64, K>,
+    tick: u64,
+}
+
+impl<K: Clone + Eq + Hash, V> LruCache<K, V> {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: HashMap::new(),
+            recency: BTreeMap::new(),
+            tick: 0,
+        }
+    }
+    pub(crate) fn capacity(&self) -> usize {
+        self.capacity
+    }
+    pub(crate) fn len(&self) -> usize {
+        self.entries.len()
+    }
+    /// Marks the entry as most recently used
+    pub(crate) fn get(&mut self, key: &K) -> Option<&V> {
+        self.tick += 1;
+        let (value, last_used) = self.entries.get_mut(key)?;
+        self.recency.remove(last_used);
+        *last_used = self.tick;
+        self.recency.insert(self.tick, key.clone());
+        Some(value)
+    }
+    /// Returns the entry that no longer fits, which is the inserted one if the capacity is zero
+    pub(crate) fn insert(&mut self, key: K, value: V) -> Option<(K, V)> {
+        if self.capacity == 0 {
+            return Some((key, value));
+        }
+        self.tick += 1;
+        if let Some((_, last_used)) = self.entries.insert(key.clone(), (value, self.tick)) {
+            self.recency.remove(&last_used);
+        }
+        self.recency.insert(self.tick, key);
+        if self.entries.len() <= self.capacity {
+            return None;
+        }
+        let (_, oldest) = self.recency.pop_first()?;
+        let (value, _) = self.entries.remove(&oldest)?;
+        Some((oldest, value))
+    }
+    pub(crate) fn clear(&mut self) {
+        self.entries.clear();
+        self.recency.clear();
+    }
+}
+
+/// [`LruCache`] shared between threads
+pub(crate) struct SharedLruCache<K, V>(Mutex<LruCache<K, V>>);
+
+impl<K: Clone + Eq + Hash, V> SharedLruCache<K, V> {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self(Mutex::new(LruCache::new(capacity)))
+    }
+    /// A panic while holding the lock can at worst lose an entry, the cache stays usable
+    pub(crate) fn lock(&self) -> MutexGuard<'_, LruCache<K, V>> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_evicts_least_recently_used() {
+        let mut cache = LruCache::new(2);
+        assert!(cache.insert("a", 1).is_none());
+        assert!(cache.insert("b", 2).is_none());
+        assert_eq!(cache.get(&"a"), Some(&1));
+        assert_eq!(cache.insert("c", 3), Some(("b", 2)));
+        assert_eq!(cache.get(&"b"), None);
+        // Replacing a value counts as a use
+        assert!(cache.insert("a", 4).is_none());
+        assert_eq!(cache.insert("d", 5), Some(("c", 3)));
+        assert_eq!(cache.get(&"a"), Some(&4));
+        assert_eq!(cache.len(), 2);
+        assert_eq!(LruCache::new(0).insert("a", 1), Some(("a", 1)));
+    }
+}
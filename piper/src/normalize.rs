@@ -0,0 +1,1491 @@
+use std::collections::HashMap;
+use std::ops::Range;
+use std::sync::Arc;
+
+//----------------------------------------------------------------
+
+/// Rewrites text into a form that reads naturally once phonemized,
+/// e.g. expanding numbers, dates and abbreviations into words
+pub trait TextNormalizer: Send + Sync {
+    fn normalize(&self, text: &str, language: &str) -> String;
+    /// Normalizes text keeping track of the input each part of the result was produced from
+    ///
+    /// The default implementation maps any changed text as a whole, so sentences can only be traced back to the entire input.
+    fn normalize_with_offsets(&self, text: &str, language: &str) -> NormalizedText {
+        NormalizedText::replaced(text, self.normalize(text, language))
+    }
+}
+
+/// Normalized text along with the ranges of the input its parts were produced from
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NormalizedText {
+    pub text: String,
+    /// Consecutive parts of `text` in order
+    parts: Vec<NormalizedPart>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct NormalizedPart {
+    normalized: Range<usize>,
+    source: Range<usize>,
+    /// The part was copied from the input unchanged, so offsets within it map one to one
+    verbatim: bool,
+}
+
+impl NormalizedText {
+    pub fn unchanged(text: &str) -> Self {
+        let mut normalized = Self::default();
+        normalized.push_part(text, 0..text.len(), true);
+        normalized
+    }
+    /// Maps all of `normalized` to all of `source`, unless the two are the same
+    pub fn replaced(source: &str, normalized: String) -> Self {
+        if source == normalized {
+            return Self::unchanged(source);
+        }
+        let mut replaced = Self::default();
+        replaced.push_part(&normalized, 0..source.len(), false);
+        replaced
+    }
+    fn push_part(&mut self, text: &str, source: Range<usize>, verbatim: bool) {
+        if text.is_empty() && source.is_empty() {
+            return;
+        }
+        let start = self.text.len();
+        self.text.push_str(text);
+        self.parts.push(NormalizedPart {
+            normalized: start..self.text.len(),
+            source,
+            verbatim,
+        });
+    }
+    /// Maps a byte range of the normalized text to the range of the input it was produced from,
+    /// widened to whole parts where text was replaced
+    pub fn source_range(&self, range: Range<usize>) -> Range<usize> {
+        let start = self
+            .parts
+            .iter()
+            .find(|part| range.start < part.normalized.end)
+            .map(|part| match part.verbatim {
+                true => {
+                    part.source.start + range.start.max(part.normalized.start)
+                        - part.normalized.start
+                }
+                false => part.source.start,
+            });
+        let end = self
+            .parts
+            .iter()
+            .rev()
+            .find(|part| range.end > part.normalized.start)
+            .map(|part| match part.verbatim {
+                true => {
+                    part.source.start + range.end.min(part.normalized.end) - part.normalized.start
+                }
+                false => part.source.end,
+            });
+        match (start, end) {
+            (Some(start), Some(end)) if start <= end => start..end,
+            (_, Some(end)) => end..end,
+            (start, None) => {
+                let start =
+                    start.unwrap_or_else(|| self.parts.last().map_or(0, |part| part.source.end));
+                start..start
+            }
+        }
+    }
+}
+
+/// Characters stripped from the start of a token before matching it
+const LEADING_PUNCTUATION: &[char] = &['(', '[', '{', '"', '\'', '«', '“', '„', '‘'];
+/// Characters stripped from the end of a token before matching it
+const TRAILING_PUNCTUATION: &[char] = &[
+    '.', ',', ';', ':', '!', '?', ')', ']', '}', '"', '\'', '»', '”', '’', '…',
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Currency {
+    Dollar,
+    Euro,
+    Pound,
+    Ruble,
+}
+
+impl Currency {
+    fn from_symbol(symbol: &str) -> Option<Self> {
+        match symbol {
+            "$" | "USD" => Some(Self::Dollar),
+            "€" | "EUR" => Some(Self::Euro),
+            "£" | "GBP" => Some(Self::Pound),
+            "₽" | "RUB" | "руб" | "руб." => Some(Self::Ruble),
+            _ => None,
+        }
+    }
+}
+
+/// Order of day and month in dates written with slashes or dots
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DateOrder {
+    DayMonthYear,
+    MonthDayYear,
+}
+
+/// A number as written in the text, the fraction keeps its digits so that leading zeros are not lost
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Number {
+    pub negative: bool,
+    pub integer: u64,
+    pub fraction: Option<String>,
+}
+
+impl Number {
+    fn is_one(&self) -> bool {
+        self.integer == 1 && self.fraction.is_none()
+    }
+}
+
+/// Language specific spelling of the entities recognized by [`RuleBasedNormalizer`]
+pub trait LanguageRules: Send + Sync {
+    fn cardinal(&self, number: &Number) -> String;
+    /// Spells `number` as an ordinal if `suffix` marks one in this language (`st` in `21st`, `-й` in `21-й`)
+    fn ordinal(&self, number: u64, suffix: &str) -> Option<String>;
+    fn time(&self, hours: u64, minutes: u64) -> String;
+    fn date(&self, year: u64, month: u64, day: u64) -> String;
+    fn currency(&self, currency: Currency, amount: &Number) -> String;
+    fn percent(&self, number: &Number) -> String;
+    fn unit(&self, number: &Number, unit: &str) -> Option<String>;
+    fn abbreviation(&self, token: &str) -> Option<String>;
+    /// Whether an abbreviation is followed by a name, like a title, rather than ending the sentence
+    /// when the next word is capitalized
+    fn precedes_name(&self, _abbreviation: &str) -> bool {
+        false
+    }
+    /// Word used for a symbol when reading out URLs and e-mail addresses
+    fn symbol(&self, symbol: char) -> Option<String>;
+    fn minus(&self) -> &str;
+    fn decimal_separator(&self) -> char;
+    fn date_order(&self) -> DateOrder;
+}
+
+/// Text normalizer that recognizes numbers, ordinals, dates, times, currency amounts, units,
+/// abbreviations, URLs and e-mail addresses and spells them out using per-language rules
+///
+/// English and Russian rules are built in, more languages can be added with [`RuleBasedNormalizer::with_rules`].
+/// Text in languages without rules is returned unchanged.
+pub struct RuleBasedNormalizer {
+    rules: HashMap<String, Arc<dyn LanguageRules>>,
+}
+
+impl Default for RuleBasedNormalizer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RuleBasedNormalizer {
+    pub fn new() -> Self {
+        Self {
+            rules: HashMap::new(),
+        }
+        .with_rules("en", Arc::new(EnglishRules))
+        .with_rules("ru", Arc::new(RussianRules))
+    }
+    /// Registers rules for a language, either a full eSpeak-ng voice name (`en-gb`) or a base language (`en`)
+    pub fn with_rules(mut self, language: &str, rules: Arc<dyn LanguageRules>) -> Self {
+        self.rules.insert(language.to_lowercase(), rules);
+        self
+    }
+    fn rules_for(&self, language: &str) -> Option<&Arc<dyn LanguageRules>> {
+        let language = language.to_lowercase();
+        self.rules.get(&language).or_else(|| {
+            let base_language = language.split(['-', '_']).next()?;
+            self.rules.get(base_language)
+        })
+    }
+}
+
+impl TextNormalizer for RuleBasedNormalizer {
+    fn normalize(&self, text: &str, language: &str) -> String {
+        self.normalize_with_offsets(text, language).text
+    }
+    fn normalize_with_offsets(&self, text: &str, language: &str) -> NormalizedText {
+        match self.rules_for(language) {
+            Some(rules) => normalize_with_rules(text, rules.as_ref()),
+            None => NormalizedText::unchanged(text),
+        }
+    }
+}
+
+fn normalize_with_rules(text: &str, rules: &dyn LanguageRules) -> NormalizedText {
+    let tokens = split_tokens(text);
+    let mut output = NormalizedText::default();
+    let mut i = 0;
+    while i < tokens.len() {
+        let (token, whitespace) = tokens[i].clone();
+        let next = tokens.get(i + 1).map(|(t, _)| &text[t.clone()]);
+        let next_is_last = i + 2 == tokens.len();
+        let (replacement, consumed_next) =
+            normalize_token(&text[token.clone()], next, next_is_last, rules);
+        let mut source = token;
+        let mut whitespace = whitespace;
+        if consumed_next {
+            i += 1;
+            source.end = tokens[i].0.end;
+            whitespace = tokens[i].1.clone();
+        }
+        let verbatim = replacement == text[source.clone()];
+        output.push_part(&replacement, source, verbatim);
+        output.push_part(&text[whitespace.clone()], whitespace, true);
+        i += 1;
+    }
+    output
+}
+
+/// Splits text into the byte ranges of its tokens, each paired with the range of the whitespace that follows it
+///
+/// Leading whitespace follows an empty token at the start of the text.
+fn split_tokens(text: &str) -> Vec<(Range<usize>, Range<usize>)> {
+    let mut tokens = Vec::new();
+    let leading_end = text.len() - text.trim_start().len();
+    if leading_end > 0 {
+        tokens.push((0..0, 0..leading_end));
+    }
+    let mut offset = leading_end;
+    while offset < text.len() {
+        let rest = &text[offset..];
+        let token_end = offset + rest.find(char::is_whitespace).unwrap_or(rest.len());
+        let after = &text[token_end..];
+        let whitespace_end = token_end
+            + after
+                .find(|c: char| !c.is_whitespace())
+                .unwrap_or(after.len());
+        tokens.push((offset..token_end, token_end..whitespace_end));
+        offset = whitespace_end;
+    }
+    tokens
+}
+
+/// Normalizes one token, returning the replacement and whether the following token was consumed
+fn normalize_token(
+    token: &str,
+    next: Option<&str>,
+    next_is_last: bool,
+    rules: &dyn LanguageRules,
+) -> (String, bool) {
+    let core = token.trim_start_matches(LEADING_PUNCTUATION);
+    let lead = &token[..token.len() - core.len()];
+
+    // Abbreviations keep their trailing dots
+    let abbreviation = core.trim_end_matches(|c| c != '.' && TRAILING_PUNCTUATION.contains(&c));
+    if let Some(expanded) = rules.abbreviation(abbreviation) {
+        let trail = &core[abbreviation.len()..];
+        // The dot of an abbreviation that ends the sentence is also its full stop
+        let next_starts_sentence = next.is_some_and(|next| {
+            let starts_capitalized = next
+                .chars()
+                .find(|c| c.is_alphanumeric())
+                .is_some_and(char::is_uppercase);
+            starts_capitalized && !rules.precedes_name(abbreviation)
+        });
+        let ends_sentence = next.is_none() || next_starts_sentence;
+        let full_stop = match abbreviation.ends_with('.') && trail.is_empty() && ends_sentence {
+            true => ".",
+            false => "",
+        };
+        return (format!("{}{}{}{}", lead, expanded, full_stop, trail), false);
+    }
+
+    let trimmed = core.trim_end_matches(TRAILING_PUNCTUATION);
+    let trail = &core[trimmed.len()..];
+    let core = trimmed;
+    if core.is_empty() {
+        return (token.to_string(), false);
+    }
+
+    if let Some(spelled) = spell_email(core, rules).or_else(|| spell_url(core, rules)) {
+        return (format!("{}{}{}", lead, spelled, trail), false);
+    }
+    if let Some(words) = parse_time(core).map(|(h, m)| rules.time(h, m)) {
+        return (format!("{}{}{}", lead, words, trail), false);
+    }
+    if let Some((year, month, day)) = parse_date(core, rules.date_order()) {
+        let words = rules.date(year, month, day);
+        return (format!("{}{}{}", lead, words, trail), false);
+    }
+
+    let separator = rules.decimal_separator();
+    // Currency symbol before the amount, `$5.20`
+    let currency_prefix = core.chars().next().and_then(|symbol| {
+        let currency = Currency::from_symbol(&symbol.to_string())?;
+        Some((currency, &core[symbol.len_utf8()..]))
+    });
+    if let Some((currency, amount)) = currency_prefix {
+        if let Some(amount) = parse_number(amount, separator) {
+            let words = rules.currency(currency, &amount);
+            return (format!("{}{}{}", lead, words, trail), false);
+        }
+    }
+
+    let number_end = number_prefix_len(core, separator);
+    let Some(number) = parse_number(&core[..number_end], separator) else {
+        return (token.to_string(), false);
+    };
+    let suffix = &core[number_end..];
+    let mut consumed_next = false;
+    let words = if suffix.is_empty() {
+        // The unit or currency may be written as the next token, `5 km` or `100 руб.`
+        let counted = next
+            .filter(|_| trail.is_empty())
+            .and_then(|next| spell_counted(&number, next, next_is_last, rules));
+        consumed_next = counted.is_some();
+        counted.unwrap_or_else(|| rules.cardinal(&number))
+    } else if suffix == "%" {
+        rules.percent(&number)
+    } else if let Some(currency) = Currency::from_symbol(suffix) {
+        rules.currency(currency, &number)
+    } else if let Some(words) = number
+        .fraction
+        .is_none()
+        .then(|| rules.ordinal(number.integer, suffix))
+        .flatten()
+    {
+        words
+    } else if let Some(words) = rules.unit(&number, suffix) {
+        words
+    } else {
+        return (token.to_string(), false);
+    };
+    let sign = if number.negative {
+        format!("{} ", rules.minus())
+    } else {
+        String::new()
+    };
+    (format!("{}{}{}{}", lead, sign, words, trail), consumed_next)
+}
+
+/// Spells a number followed by a separate unit or currency token
+///
+/// The dot of an abbreviated unit is dropped unless it is the last token, where it also ends the sentence.
+fn spell_counted(
+    number: &Number,
+    unit_token: &str,
+    keep_dot: bool,
+    rules: &dyn LanguageRules,
+) -> Option<String> {
+    let unit = unit_token.trim_end_matches(TRAILING_PUNCTUATION);
+    let trail = &unit_token[unit.len()..];
+    let words = match Currency::from_symbol(unit) {
+        Some(currency) => rules.currency(currency, number),
+        None => rules.unit(number, unit)?,
+    };
+    let trail = match keep_dot {
+        true => trail,
+        false => trail.strip_prefix('.').unwrap_or(trail),
+    };
+    Some(format!("{}{}", words, trail))
+}
+
+/// Length of the leading number in `text`, including sign, digit grouping and decimal part
+fn number_prefix_len(text: &str, decimal_separator: char) -> usize {
+    let mut end = 0;
+    let mut seen_digit = false;
+    let mut seen_separator = false;
+    for (i, c) in text.char_indices() {
+        if c.is_ascii_digit() {
+            seen_digit = true;
+            end = i + 1;
+        } else if i == 0 && (c == '-' || c == '−') {
+            continue;
+        } else if c == decimal_separator && seen_digit && !seen_separator {
+            seen_separator = true;
+        } else if c == ',' && decimal_separator != ',' && seen_digit && !seen_separator {
+            // Digit grouping, `1,000`
+            continue;
+        } else {
+            break;
+        }
+    }
+    end
+}
+
+fn parse_number(text: &str, decimal_separator: char) -> Option<Number> {
+    let (negative, text) = match text.strip_prefix(['-', '−']) {
+        Some(rest) => (true, rest),
+        None => (false, text),
+    };
+    let (integer, fraction) = match text.split_once(decimal_separator) {
+        Some((integer, fraction)) => (integer, Some(fraction)),
+        None => (text, None),
+    };
+    let integer = if decimal_separator != ',' && integer.contains(',') {
+        let groups = Vec::from_iter(integer.split(','));
+        let valid_grouping = !groups[0].is_empty()
+            && groups[0].len() <= 3
+            && groups[1..].iter().all(|g| g.len() == 3);
+        if !valid_grouping {
+            return None;
+        }
+        groups.concat()
+    } else {
+        integer.to_string()
+    };
+    if integer.is_empty() || !integer.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    if let Some(fraction) = fraction {
+        if fraction.is_empty() || !fraction.chars().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+    }
+    Some(Number {
+        negative,
+        integer: integer.parse().ok()?,
+        fraction: fraction.map(|f| f.to_string()),
+    })
+}
+
+fn parse_time(text: &str) -> Option<(u64, u64)> {
+    let (hours, minutes) = text.split_once(':')?;
+    if !(1..=2).contains(&hours.len()) || minutes.len() != 2 {
+        return None;
+    }
+    let hours: u64 = hours.parse().ok()?;
+    let minutes: u64 = minutes.parse().ok()?;
+    (hours < 24 && minutes < 60).then_some((hours, minutes))
+}
+
+fn parse_date(text: &str, order: DateOrder) -> Option<(u64, u64, u64)> {
+    let parts = |separator: char| -> Option<Vec<u64>> {
+        let parts = Vec::from_iter(text.split(separator));
+        if parts.len() != 3 || parts.iter().any(|p| p.is_empty() || p.len() > 4) {
+            return None;
+        }
+        parts.iter().map(|p| p.parse().ok()).collect()
+    };
+    let (year, month, day) = if let Some(p) = parts('-') {
+        (p[0], p[1], p[2])
+    } else if let Some(p) = parts('.').or_else(|| parts('/')) {
+        match order {
+            DateOrder::DayMonthYear => (p[2], p[1], p[0]),
+            DateOrder::MonthDayYear => (p[2], p[0], p[1]),
+        }
+    } else {
+        return None;
+    };
+    let valid =
+        (1000..=9999).contains(&year) && (1..=12).contains(&month) && (1..=31).contains(&day);
+    valid.then_some((year, month, day))
+}
+
+fn spell_email(text: &str, rules: &dyn LanguageRules) -> Option<String> {
+    let (user, domain) = text.split_once('@')?;
+    if user.is_empty() || !domain.contains('.') || domain.starts_with('.') || domain.ends_with('.')
+    {
+        return None;
+    }
+    Some(spell_symbols(text, rules))
+}
+
+fn spell_url(text: &str, rules: &dyn LanguageRules) -> Option<String> {
+    let address = ["https://", "http://"]
+        .iter()
+        .find_map(|scheme| text.strip_prefix(scheme))
+        .or_else(|| text.starts_with("www.").then_some(text))?;
+    let address = address.trim_end_matches('/');
+    if address.is_empty() {
+        return None;
+    }
+    Some(spell_symbols(address, rules))
+}
+
+/// Replaces the symbols in URLs and e-mail addresses with words, keeping the parts between them
+fn spell_symbols(text: &str, rules: &dyn LanguageRules) -> String {
+    let mut words: Vec<String> = Vec::new();
+    let mut part = String::new();
+    for c in text.chars() {
+        if c.is_alphanumeric() {
+            part.push(c);
+            continue;
+        }
+        if !part.is_empty() {
+            words.push(std::mem::take(&mut part));
+        }
+        if let Some(word) = rules.symbol(c) {
+            words.push(word);
+        }
+    }
+    if !part.is_empty() {
+        words.push(part);
+    }
+    words.join(" ")
+}
+
+/// Picks the Russian plural form for `n`: (один) рубль, (два) рубля, (пять) рублей
+fn russian_plural(n: u64, forms: [&str; 3]) -> &str {
+    match (n % 10, n % 100) {
+        (_, 11..=14) => forms[2],
+        (1, _) => forms[0],
+        (2..=4, _) => forms[1],
+        _ => forms[2],
+    }
+}
+
+// ==============================
+// English
+
+const EN_ONES: [&str; 20] = [
+    "zero",
+    "one",
+    "two",
+    "three",
+    "four",
+    "five",
+    "six",
+    "seven",
+    "eight",
+    "nine",
+    "ten",
+    "eleven",
+    "twelve",
+    "thirteen",
+    "fourteen",
+    "fifteen",
+    "sixteen",
+    "seventeen",
+    "eighteen",
+    "nineteen",
+];
+const EN_TENS: [&str; 10] = [
+    "", "", "twenty", "thirty", "forty", "fifty", "sixty", "seventy", "eighty", "ninety",
+];
+const EN_SCALES: [&str; 7] = [
+    "",
+    "thousand",
+    "million",
+    "billion",
+    "trillion",
+    "quadrillion",
+    "quintillion",
+];
+const EN_MONTHS: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+/// (symbol, singular, plural)
+const EN_UNITS: [(&str, &str, &str); 18] = [
+    ("km", "kilometer", "kilometers"),
+    ("m", "meter", "meters"),
+    ("cm", "centimeter", "centimeters"),
+    ("mm", "millimeter", "millimeters"),
+    ("kg", "kilogram", "kilograms"),
+    ("g", "gram", "grams"),
+    ("mg", "milligram", "milligrams"),
+    ("l", "liter", "liters"),
+    ("ml", "milliliter", "milliliters"),
+    ("h", "hour", "hours"),
+    ("min", "minute", "minutes"),
+    ("sec", "second", "seconds"),
+    ("ms", "millisecond", "milliseconds"),
+    ("km/h", "kilometer per hour", "kilometers per hour"),
+    ("mph", "mile per hour", "miles per hour"),
+    ("°C", "degree Celsius", "degrees Celsius"),
+    ("°F", "degree Fahrenheit", "degrees Fahrenheit"),
+    ("kB", "kilobyte", "kilobytes"),
+];
+const EN_ABBREVIATIONS: [(&str, &str); 10] = [
+    ("Dr.", "Doctor"),
+    ("Mr.", "Mister"),
+    ("Mrs.", "Missus"),
+    ("Ms.", "Miz"),
+    ("Prof.", "Professor"),
+    ("etc.", "et cetera"),
+    ("e.g.", "for example"),
+    ("i.e.", "that is"),
+    ("vs.", "versus"),
+    ("approx.", "approximately"),
+];
+
+pub struct EnglishRules;
+
+impl EnglishRules {
+    fn integer(n: u64) -> String {
+        if n == 0 {
+            return EN_ONES[0].to_string();
+        }
+        let mut groups = Vec::new();
+        let mut rest = n;
+        let mut scale = 0;
+        while rest > 0 {
+            let group = rest % 1000;
+            if group > 0 {
+                let words = Self::below_thousand(group);
+                groups.push(match EN_SCALES[scale] {
+                    "" => words,
+                    scale_word => format!("{} {}", words, scale_word),
+                });
+            }
+            rest /= 1000;
+            scale += 1;
+        }
+        groups.reverse();
+        groups.join(" ")
+    }
+    fn below_thousand(n: u64) -> String {
+        let hundreds = n / 100;
+        let rest = n % 100;
+        let rest_words = match rest {
+            0 => String::new(),
+            1..=19 => EN_ONES[rest as usize].to_string(),
+            _ if rest.is_multiple_of(10) => EN_TENS[(rest / 10) as usize].to_string(),
+            _ => format!(
+                "{}-{}",
+                EN_TENS[(rest / 10) as usize],
+                EN_ONES[(rest % 10) as usize]
+            ),
+        };
+        match (hundreds, rest) {
+            (0, _) => rest_words,
+            (_, 0) => format!("{} hundred", EN_ONES[hundreds as usize]),
+            _ => format!("{} hundred {}", EN_ONES[hundreds as usize], rest_words),
+        }
+    }
+    fn ordinal_words(n: u64) -> String {
+        let cardinal = Self::integer(n);
+        let split_at = cardinal.rfind([' ', '-']).map_or(0, |i| i + 1);
+        let (head, last) = cardinal.split_at(split_at);
+        let last = match last {
+            "one" => "first".to_string(),
+            "two" => "second".to_string(),
+            "three" => "third".to_string(),
+            "five" => "fifth".to_string(),
+            "eight" => "eighth".to_string(),
+            "nine" => "ninth".to_string(),
+            "twelve" => "twelfth".to_string(),
+            _ if last.ends_with('y') => format!("{}ieth", &last[..last.len() - 1]),
+            _ => format!("{}th", last),
+        };
+        format!("{}{}", head, last)
+    }
+    fn year(year: u64) -> String {
+        match (year / 100, year % 100) {
+            _ if year.is_multiple_of(1000) => Self::integer(year),
+            (20, 0..=9) => Self::integer(year),
+            (century, 0) => format!("{} hundred", Self::integer(century)),
+            (century, rest @ 1..=9) => {
+                format!("{} oh {}", Self::integer(century), EN_ONES[rest as usize])
+            }
+            (century, rest) => format!("{} {}", Self::integer(century), Self::integer(rest)),
+        }
+    }
+}
+
+impl LanguageRules for EnglishRules {
+    fn cardinal(&self, number: &Number) -> String {
+        let integer = Self::integer(number.integer);
+        match number.fraction {
+            Some(ref fraction) => {
+                let digits = Vec::from_iter(
+                    fraction
+                        .chars()
+                        .filter_map(|c| c.to_digit(10))
+                        .map(|d| EN_ONES[d as usize]),
+                );
+                format!("{} point {}", integer, digits.join(" "))
+            }
+            None => integer,
+        }
+    }
+    fn ordinal(&self, number: u64, suffix: &str) -> Option<String> {
+        let expected = match (number % 10, number % 100) {
+            (_, 11..=13) => "th",
+            (1, _) => "st",
+            (2, _) => "nd",
+            (3, _) => "rd",
+            _ => "th",
+        };
+        suffix
+            .eq_ignore_ascii_case(expected)
+            .then(|| Self::ordinal_words(number))
+    }
+    fn time(&self, hours: u64, minutes: u64) -> String {
+        let hours = Self::integer(hours);
+        match minutes {
+            0 => format!("{} o'clock", hours),
+            1..=9 => format!("{} oh {}", hours, EN_ONES[minutes as usize]),
+            _ => format!("{} {}", hours, Self::integer(minutes)),
+        }
+    }
+    fn date(&self, year: u64, month: u64, day: u64) -> String {
+        format!(
+            "{} {}, {}",
+            EN_MONTHS[(month - 1) as usize],
+            Self::ordinal_words(day),
+            Self::year(year)
+        )
+    }
+    fn currency(&self, currency: Currency, amount: &Number) -> String {
+        let (major, majors, minor, minors) = match currency {
+            Currency::Dollar => ("dollar", "dollars", "cent", "cents"),
+            Currency::Euro => ("euro", "euros", "cent", "cents"),
+            Currency::Pound => ("pound", "pounds", "penny", "pence"),
+            Currency::Ruble => ("ruble", "rubles", "kopeck", "kopecks"),
+        };
+        let cents = minor_units(amount);
+        let mut words = format!(
+            "{} {}",
+            Self::integer(amount.integer),
+            if amount.integer == 1 { major } else { majors }
+        );
+        if cents > 0 {
+            words.push_str(&format!(
+                " and {} {}",
+                Self::integer(cents),
+                if cents == 1 { minor } else { minors }
+            ));
+        }
+        words
+    }
+    fn percent(&self, number: &Number) -> String {
+        format!("{} percent", self.cardinal(number))
+    }
+    fn unit(&self, number: &Number, unit: &str) -> Option<String> {
+        let (_, singular, plural) = EN_UNITS.iter().find(|(symbol, _, _)| *symbol == unit)?;
+        let unit_words = if number.is_one() { singular } else { plural };
+        Some(format!("{} {}", self.cardinal(number), unit_words))
+    }
+    fn abbreviation(&self, token: &str) -> Option<String> {
+        EN_ABBREVIATIONS
+            .iter()
+            .find(|(abbreviation, _)| *abbreviation == token)
+            .map(|(_, expanded)| expanded.to_string())
+    }
+    fn precedes_name(&self, abbreviation: &str) -> bool {
+        matches!(abbreviation, "Dr." | "Mr." | "Mrs." | "Ms." | "Prof.")
+    }
+    fn symbol(&self, symbol: char) -> Option<String> {
+        let word = match symbol {
+            '.' => "dot",
+            '@' => "at",
+            '/' => "slash",
+            ':' => "colon",
+            '-' => "dash",
+            '_' => "underscore",
+            '?' => "question mark",
+            '=' => "equals",
+            '&' => "and",
+            _ => return None,
+        };
+        Some(word.to_string())
+    }
+    fn minus(&self) -> &str {
+        "minus"
+    }
+    fn decimal_separator(&self) -> char {
+        '.'
+    }
+    fn date_order(&self) -> DateOrder {
+        DateOrder::MonthDayYear
+    }
+}
+
+/// Amount after the decimal separator in hundredths, `5.2` is 20 cents
+fn minor_units(amount: &Number) -> u64 {
+    let Some(ref fraction) = amount.fraction else {
+        return 0;
+    };
+    let digits = Vec::from_iter(fraction.chars().filter_map(|c| c.to_digit(10)).take(2));
+    match digits[..] {
+        [tens] => tens as u64 * 10,
+        [tens, ones] => tens as u64 * 10 + ones as u64,
+        _ => 0,
+    }
+}
+
+// ==============================
+// Russian
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Gender {
+    Masculine,
+    Feminine,
+    Neuter,
+}
+
+/// Grammatical form of Russian ordinals needed by the rules
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OrdinalForm {
+    MasculineNominative,
+    FeminineNominative,
+    NeuterNominative,
+    Genitive,
+}
+
+const RU_ONES: [&str; 20] = [
+    "ноль",
+    "один",
+    "два",
+    "три",
+    "четыре",
+    "пять",
+    "шесть",
+    "семь",
+    "восемь",
+    "девять",
+    "десять",
+    "одиннадцать",
+    "двенадцать",
+    "тринадцать",
+    "четырнадцать",
+    "пятнадцать",
+    "шестнадцать",
+    "семнадцать",
+    "восемнадцать",
+    "девятнадцать",
+];
+const RU_TENS: [&str; 10] = [
+    "",
+    "",
+    "двадцать",
+    "тридцать",
+    "сорок",
+    "пятьдесят",
+    "шестьдесят",
+    "семьдесят",
+    "восемьдесят",
+    "девяносто",
+];
+const RU_HUNDREDS: [&str; 10] = [
+    "",
+    "сто",
+    "двести",
+    "триста",
+    "четыреста",
+    "пятьсот",
+    "шестьсот",
+    "семьсот",
+    "восемьсот",
+    "девятьсот",
+];
+/// Plural forms and gender of each power of a thousand
+const RU_SCALES: [([&str; 3], Gender); 7] = [
+    (["", "", ""], Gender::Masculine),
+    (["тысяча", "тысячи", "тысяч"], Gender::Feminine),
+    (["миллион", "миллиона", "миллионов"], Gender::Masculine),
+    (["миллиард", "миллиарда", "миллиардов"], Gender::Masculine),
+    (["триллион", "триллиона", "триллионов"], Gender::Masculine),
+    (
+        ["квадриллион", "квадриллиона", "квадриллионов"],
+        Gender::Masculine,
+    ),
+    (
+        ["квинтиллион", "квинтиллиона", "квинтиллионов"],
+        Gender::Masculine,
+    ),
+];
+const RU_MONTHS_GENITIVE: [&str; 12] = [
+    "января",
+    "февраля",
+    "марта",
+    "апреля",
+    "мая",
+    "июня",
+    "июля",
+    "августа",
+    "сентября",
+    "октября",
+    "ноября",
+    "декабря",
+];
+
+/// Ordinal stems of 50 to 80 and 500 to 900, which use the genitive of the first part
+const RU_ORDINAL_TENS: [&str; 4] = ["пятидесят", "шестидесят", "семидесят", "восьмидесят"];
+const RU_ORDINAL_HUNDREDS: [&str; 5] = ["пятисот", "шестисот", "семисот", "восьмисот", "девятисот"];
+/// First parts of ordinals that end in a power of a thousand: двадцатиоднотысячный, трёхсотмиллионный
+const RU_COMPOUND_ONES: [&str; 20] = [
+    "",
+    "одно",
+    "двух",
+    "трёх",
+    "четырёх",
+    "пяти",
+    "шести",
+    "семи",
+    "восьми",
+    "девяти",
+    "десяти",
+    "одиннадцати",
+    "двенадцати",
+    "тринадцати",
+    "четырнадцати",
+    "пятнадцати",
+    "шестнадцати",
+    "семнадцати",
+    "восемнадцати",
+    "девятнадцати",
+];
+const RU_COMPOUND_TENS: [&str; 10] = [
+    "",
+    "",
+    "двадцати",
+    "тридцати",
+    "сорока",
+    "пятидесяти",
+    "шестидесяти",
+    "семидесяти",
+    "восьмидесяти",
+    "девяноста",
+];
+const RU_COMPOUND_HUNDREDS: [&str; 10] = [
+    "",
+    "сто",
+    "двухсот",
+    "трёхсот",
+    "четырёхсот",
+    "пятисот",
+    "шестисот",
+    "семисот",
+    "восьмисот",
+    "девятисот",
+];
+
+/// Declension class of an ordinal stem: первый, второй, третий
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OrdinalClass {
+    Hard,
+    Stressed,
+    Soft,
+}
+
+/// (symbol, plural forms, gender)
+const RU_UNITS: [(&str, [&str; 3], Gender); 16] = [
+    (
+        "км",
+        ["километр", "километра", "километров"],
+        Gender::Masculine,
+    ),
+    ("м", ["метр", "метра", "метров"], Gender::Masculine),
+    (
+        "см",
+        ["сантиметр", "сантиметра", "сантиметров"],
+        Gender::Masculine,
+    ),
+    (
+        "мм",
+        ["миллиметр", "миллиметра", "миллиметров"],
+        Gender::Masculine,
+    ),
+    (
+        "кг",
+        ["килограмм", "килограмма", "килограммов"],
+        Gender::Masculine,
+    ),
+    ("л", ["литр", "литра", "литров"], Gender::Masculine),
+    (
+        "мл",
+        ["миллилитр", "миллилитра", "миллилитров"],
+        Gender::Masculine,
+    ),
+    ("ч", ["час", "часа", "часов"], Gender::Masculine),
+    ("мин", ["минута", "минуты", "минут"], Gender::Feminine),
+    ("сек", ["секунда", "секунды", "секунд"], Gender::Feminine),
+    (
+        "км/ч",
+        ["километр в час", "километра в час", "километров в час"],
+        Gender::Masculine,
+    ),
+    (
+        "°C",
+        ["градус Цельсия", "градуса Цельсия", "градусов Цельсия"],
+        Gender::Masculine,
+    ),
+    ("тыс", ["тысяча", "тысячи", "тысяч"], Gender::Feminine),
+    (
+        "млн",
+        ["миллион", "миллиона", "миллионов"],
+        Gender::Masculine,
+    ),
+    (
+        "млрд",
+        ["миллиард", "миллиарда", "миллиардов"],
+        Gender::Masculine,
+    ),
+    ("коп", ["копейка", "копейки", "копеек"], Gender::Feminine),
+];
+const RU_ABBREVIATIONS: [(&str, &str); 8] = [
+    ("т.е.", "то есть"),
+    ("т.д.", "так далее"),
+    ("т.п.", "тому подобное"),
+    ("т.к.", "так как"),
+    ("см.", "смотри"),
+    ("ул.", "улица"),
+    ("др.", "другие"),
+    ("пр.", "прочие"),
+];
+
+pub struct RussianRules;
+
+impl RussianRules {
+    fn integer(n: u64, gender: Gender) -> String {
+        if n == 0 {
+            return RU_ONES[0].to_string();
+        }
+        let mut groups = Vec::new();
+        let mut rest = n;
+        let mut scale = 0;
+        while rest > 0 {
+            let group = rest % 1000;
+            if group > 0 {
+                let (forms, scale_gender) = RU_SCALES[scale];
+                let group_gender = if scale == 0 { gender } else { scale_gender };
+                let words = Self::below_thousand(group, group_gender);
+                groups.push(match scale {
+                    0 => words,
+                    _ => format!("{} {}", words, russian_plural(group, forms)),
+                });
+            }
+            rest /= 1000;
+            scale += 1;
+        }
+        groups.reverse();
+        groups.join(" ")
+    }
+    fn below_thousand(n: u64, gender: Gender) -> String {
+        let mut words = Vec::new();
+        if n >= 100 {
+            words.push(RU_HUNDREDS[(n / 100) as usize]);
+        }
+        let rest = n % 100;
+        let ones = if rest < 20 {
+            rest
+        } else {
+            words.push(RU_TENS[(rest / 10) as usize]);
+            rest % 10
+        };
+        if ones > 0 || n == 0 {
+            words.push(match (ones, gender) {
+                (1, Gender::Feminine) => "одна",
+                (1, Gender::Neuter) => "одно",
+                (2, Gender::Feminine) => "две",
+                _ => RU_ONES[ones as usize],
+            });
+        }
+        words.join(" ")
+    }
+    /// Stem and declension class of the ordinal for a number below a thousand that has a single non-zero "part"
+    fn ordinal_stem(n: u64) -> (String, OrdinalClass) {
+        let (stem, class) = match n {
+            1 => ("перв", OrdinalClass::Hard),
+            2 => ("втор", OrdinalClass::Stressed),
+            3 => ("трет", OrdinalClass::Soft),
+            4 => ("четвёрт", OrdinalClass::Hard),
+            6 => ("шест", OrdinalClass::Stressed),
+            7 => ("седьм", OrdinalClass::Stressed),
+            8 => ("восьм", OrdinalClass::Stressed),
+            40 => ("сороков", OrdinalClass::Stressed),
+            90 => ("девяност", OrdinalClass::Hard),
+            100 => ("сот", OrdinalClass::Hard),
+            200 => ("двухсот", OrdinalClass::Hard),
+            300 => ("трёхсот", OrdinalClass::Hard),
+            400 => ("четырёхсот", OrdinalClass::Hard),
+            n if n >= 500 => (
+                RU_ORDINAL_HUNDREDS[(n / 100 - 5) as usize],
+                OrdinalClass::Hard,
+            ),
+            n if n >= 50 => (RU_ORDINAL_TENS[(n / 10 - 5) as usize], OrdinalClass::Hard),
+            n if n >= 20 => (RU_TENS[(n / 10) as usize], OrdinalClass::Hard),
+            n => (RU_ONES[n as usize], OrdinalClass::Hard),
+        };
+        // Cardinals ending in a soft sign drop it: пять → пятый, двадцать → двадцатый
+        (stem.trim_end_matches('ь').to_string(), class)
+    }
+    /// First part of the ordinal of `n` thousands, millions, ..., a single one has none: тысячный, двухтысячный
+    fn compound_prefix(n: u64) -> String {
+        if n == 1 {
+            return String::new();
+        }
+        let rest = n % 100;
+        let (tens, ones) = match rest < 20 {
+            true => ("", RU_COMPOUND_ONES[rest as usize]),
+            false => (
+                RU_COMPOUND_TENS[(rest / 10) as usize],
+                RU_COMPOUND_ONES[(rest % 10) as usize],
+            ),
+        };
+        format!(
+            "{}{}{}",
+            RU_COMPOUND_HUNDREDS[(n / 100) as usize],
+            tens,
+            ones
+        )
+    }
+    fn ordinal_words(n: u64, form: OrdinalForm) -> String {
+        let (prefix, stem, class) = if n == 0 {
+            (0, "нулев".to_string(), OrdinalClass::Stressed)
+        } else if n.is_multiple_of(1000) {
+            // The last non-zero group and its power of a thousand make one word
+            let mut scale = 0;
+            let mut power = 1;
+            while (n / power).is_multiple_of(1000) {
+                scale += 1;
+                power *= 1000;
+            }
+            let group = n / power % 1000;
+            let (forms, _) = RU_SCALES[scale];
+            let stem = format!(
+                "{}{}н",
+                Self::compound_prefix(group),
+                forms[0].trim_end_matches('а')
+            );
+            (n - group * power, stem, OrdinalClass::Hard)
+        } else {
+            let rest = n % 1000;
+            let last = match rest % 100 {
+                0 => rest,
+                tens_and_ones if tens_and_ones < 20 || tens_and_ones.is_multiple_of(10) => {
+                    tens_and_ones
+                }
+                tens_and_ones => tens_and_ones % 10,
+            };
+            let (stem, class) = Self::ordinal_stem(last);
+            (n - last, stem, class)
+        };
+        let ending = match (class, form) {
+            (OrdinalClass::Hard, OrdinalForm::MasculineNominative) => "ый",
+            (OrdinalClass::Stressed, OrdinalForm::MasculineNominative) => "ой",
+            (OrdinalClass::Soft, OrdinalForm::MasculineNominative) => "ий",
+            (OrdinalClass::Soft, OrdinalForm::FeminineNominative) => "ья",
+            (_, OrdinalForm::FeminineNominative) => "ая",
+            (OrdinalClass::Soft, OrdinalForm::NeuterNominative) => "ье",
+            (_, OrdinalForm::NeuterNominative) => "ое",
+            (OrdinalClass::Soft, OrdinalForm::Genitive) => "ьего",
+            (_, OrdinalForm::Genitive) => "ого",
+        };
+        let word = format!("{}{}", stem, ending);
+        if prefix == 0 {
+            word
+        } else {
+            format!("{} {}", Self::integer(prefix, Gender::Masculine), word)
+        }
+    }
+    fn number(number: &Number, gender: Gender) -> String {
+        let integer = Self::integer(number.integer, gender);
+        let Some(ref fraction) = number.fraction else {
+            return integer;
+        };
+        let denominator = match fraction.len() {
+            1 => ["десятая", "десятых"],
+            2 => ["сотая", "сотых"],
+            3 => ["тысячная", "тысячных"],
+            _ => {
+                let digits = Vec::from_iter(
+                    fraction
+                        .chars()
+                        .filter_map(|c| c.to_digit(10))
+                        .map(|d| RU_ONES[d as usize]),
+                );
+                return format!("{} запятая {}", integer, digits.join(" "));
+            }
+        };
+        let numerator: u64 = fraction.parse().unwrap_or(0);
+        let whole = if number.integer % 10 == 1 && number.integer % 100 != 11 {
+            "целая"
+        } else {
+            "целых"
+        };
+        let part = if numerator % 10 == 1 && numerator % 100 != 11 {
+            denominator[0]
+        } else {
+            denominator[1]
+        };
+        format!(
+            "{} {} {} {}",
+            Self::integer(number.integer, Gender::Feminine),
+            whole,
+            Self::integer(numerator, Gender::Feminine),
+            part
+        )
+    }
+    /// Number followed by a counted noun in the right plural form, fractions take the genitive singular
+    fn counted(number: &Number, forms: [&str; 3], gender: Gender) -> String {
+        let noun = if number.fraction.is_some() {
+            forms[1]
+        } else {
+            russian_plural(number.integer, forms)
+        };
+        format!("{} {}", Self::number(number, gender), noun)
+    }
+}
+
+impl LanguageRules for RussianRules {
+    fn cardinal(&self, number: &Number) -> String {
+        Self::number(number, Gender::Masculine)
+    }
+    fn ordinal(&self, number: u64, suffix: &str) -> Option<String> {
+        let form = match suffix {
+            "-й" | "-ый" | "-ой" | "-ий" => OrdinalForm::MasculineNominative,
+            "-я" | "-ая" => OrdinalForm::FeminineNominative,
+            "-е" | "-ое" => OrdinalForm::NeuterNominative,
+            "-го" | "-ого" => OrdinalForm::Genitive,
+            _ => return None,
+        };
+        Some(Self::ordinal_words(number, form))
+    }
+    fn time(&self, hours: u64, minutes: u64) -> String {
+        let hours_words = format!(
+            "{} {}",
+            Self::integer(hours, Gender::Masculine),
+            russian_plural(hours, ["час", "часа", "часов"])
+        );
+        if minutes == 0 {
+            return hours_words;
+        }
+        format!(
+            "{} {} {}",
+            hours_words,
+            Self::integer(minutes, Gender::Feminine),
+            russian_plural(minutes, ["минута", "минуты", "минут"])
+        )
+    }
+    fn date(&self, year: u64, month: u64, day: u64) -> String {
+        format!(
+            "{} {} {} года",
+            Self::ordinal_words(day, OrdinalForm::NeuterNominative),
+            RU_MONTHS_GENITIVE[(month - 1) as usize],
+            Self::ordinal_words(year, OrdinalForm::Genitive)
+        )
+    }
+    fn currency(&self, currency: Currency, amount: &Number) -> String {
+        let (major, major_gender, minor, minor_gender) = match currency {
+            Currency::Dollar => (
+                ["доллар", "доллара", "долларов"],
+                Gender::Masculine,
+                ["цент", "цента", "центов"],
+                Gender::Masculine,
+            ),
+            Currency::Euro => (
+                ["евро", "евро", "евро"],
+                Gender::Neuter,
+                ["цент", "цента", "центов"],
+                Gender::Masculine,
+            ),
+            Currency::Pound => (
+                ["фунт", "фунта", "фунтов"],
+                Gender::Masculine,
+                ["пенс", "пенса", "пенсов"],
+                Gender::Masculine,
+            ),
+            Currency::Ruble => (
+                ["рубль", "рубля", "рублей"],
+                Gender::Masculine,
+                ["копейка", "копейки", "копеек"],
+                Gender::Feminine,
+            ),
+        };
+        let whole = Number {
+            fraction: None,
+            ..amount.clone()
+        };
+        let mut words = Self::counted(&whole, major, major_gender);
+        let cents = minor_units(amount);
+        if cents > 0 {
+            let cents = Number {
+                negative: false,
+                integer: cents,
+                fraction: None,
+            };
+            words.push(' ');
+            words.push_str(&Self::counted(&cents, minor, minor_gender));
+        }
+        words
+    }
+    fn percent(&self, number: &Number) -> String {
+        Self::counted(
+            number,
+            ["процент", "процента", "процентов"],
+            Gender::Masculine,
+        )
+    }
+    fn unit(&self, number: &Number, unit: &str) -> Option<String> {
+        let unit = match unit {
+            "km" => "км",
+            "m" => "м",
+            "cm" => "см",
+            "mm" => "мм",
+            "kg" => "кг",
+            "l" => "л",
+            "ml" => "мл",
+            "km/h" => "км/ч",
+            unit => unit,
+        };
+        let (_, forms, gender) = RU_UNITS.iter().find(|(symbol, _, _)| *symbol == unit)?;
+        Some(Self::counted(number, *forms, *gender))
+    }
+    fn abbreviation(&self, token: &str) -> Option<String> {
+        RU_ABBREVIATIONS
+            .iter()
+            .find(|(abbreviation, _)| *abbreviation == token)
+            .map(|(_, expanded)| expanded.to_string())
+    }
+    fn precedes_name(&self, abbreviation: &str) -> bool {
+        matches!(abbreviation, "см." | "ул.")
+    }
+    fn symbol(&self, symbol: char) -> Option<String> {
+        let word = match symbol {
+            '.' => "точка",
+            '@' => "собака",
+            '/' => "слэш",
+            ':' => "двоеточие",
+            '-' => "дефис",
+            '_' => "нижнее подчёркивание",
+            '?' => "вопросительный знак",
+            '=' => "равно",
+            '&' => "и",
+            _ => return None,
+        };
+        Some(word.to_string())
+    }
+    fn minus(&self) -> &str {
+        "минус"
+    }
+    fn decimal_separator(&self) -> char {
+        ','
+    }
+    fn date_order(&self) -> DateOrder {
+        DateOrder::DayMonthYear
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn en(text: &str) -> String {
+        RuleBasedNormalizer::new().normalize(text, "en-us")
+    }
+
+    fn ru(text: &str) -> String {
+        RuleBasedNormalizer::new().normalize(text, "ru")
+    }
+
+    #[test]
+    fn test_maps_normalized_ranges_to_source() {
+        let text = "It costs $5 at 10:00, ok";
+        let normalized = RuleBasedNormalizer::new().normalize_with_offsets(text, "en");
+        assert_eq!(normalized.text, "It costs five dollars at ten o'clock, ok");
+        let source_of = |part: &str| {
+            let start = normalized.text.find(part).unwrap();
+            &text[normalized.source_range(start..start + part.len())]
+        };
+        assert_eq!(source_of("It costs"), "It costs");
+        assert_eq!(source_of("dollars"), "$5");
+        assert_eq!(source_of("at ten o'clock, ok"), "at 10:00, ok");
+        assert_eq!(source_of("ok"), "ok");
+
+        let unknown = RuleBasedNormalizer::new().normalize_with_offsets(text, "xx");
+        assert_eq!(&text[unknown.source_range(3..8)], "costs");
+    }
+
+    #[test]
+    fn test_leading_and_trailing_whitespace() {
+        assert_eq!(en("  I have 5 km."), "  I have five kilometers.");
+        assert_eq!(en("\nHello"), "\nHello");
+        assert_eq!(en("at 10:00 \n"), "at ten o'clock \n");
+        assert_eq!(ru("\t5 мин "), "\tпять минут ");
+        assert_eq!(en("   "), "   ");
+        assert_eq!(en(" apples, etc.\n"), " apples, et cetera.\n");
+        assert_eq!(ru("\tи т.д. "), "\tи так далее. ");
+        let normalized = RuleBasedNormalizer::new().normalize_with_offsets(" 5 km", "en");
+        let start = normalized.text.find("five").unwrap();
+        assert_eq!(normalized.source_range(start..normalized.text.len()), 1..5);
+    }
+
+    #[test]
+    fn test_english_numbers() {
+        assert_eq!(en("I have 21 cats"), "I have twenty-one cats");
+        assert_eq!(
+            en("1,234,567"),
+            "one million two hundred thirty-four thousand five hundred sixty-seven"
+        );
+        assert_eq!(en("pi is 3.14."), "pi is three point one four.");
+        assert_eq!(en("It is -5 outside"), "It is minus five outside");
+        assert_eq!(en("the 21st and 12th"), "the twenty-first and twelfth");
+    }
+
+    #[test]
+    fn test_english_dates_and_times() {
+        assert_eq!(en("at 10:00,"), "at ten o'clock,");
+        assert_eq!(en("at 11:05"), "at eleven oh five");
+        assert_eq!(en("2023-06-14"), "June fourteenth, twenty twenty-three");
+        assert_eq!(en("06/01/2005"), "June first, two thousand five");
+        assert_eq!(en("1900-01-01"), "January first, nineteen hundred");
+    }
+
+    #[test]
+    fn test_english_currency_and_units() {
+        assert_eq!(en("$5.20"), "five dollars and twenty cents");
+        assert_eq!(en("It costs 1 €."), "It costs one euro.");
+        assert_eq!(en("50%"), "fifty percent");
+        assert_eq!(en("5 km away"), "five kilometers away");
+        assert_eq!(en("1kg"), "one kilogram");
+    }
+
+    #[test]
+    fn test_english_abbreviations_urls_emails() {
+        assert_eq!(en("Dr. Smith, etc."), "Doctor Smith, et cetera.");
+        assert_eq!(
+            en("Apples, etc. Then pears"),
+            "Apples, et cetera. Then pears"
+        );
+        assert_eq!(en("etc. and more"), "et cetera and more");
+        assert_eq!(en("Ask Dr. Smith"), "Ask Doctor Smith");
+        assert_eq!(en("ask the Dr."), "ask the Doctor.");
+        assert_eq!(en("e.g., this"), "for example, this");
+        assert_eq!(
+            en("Mail john.doe@example.com or visit https://example.com/docs."),
+            "Mail john dot doe at example dot com or visit example dot com slash docs."
+        );
+    }
+
+    #[test]
+    fn test_russian() {
+        assert_eq!(ru("было уже 10:00 а"), "было уже десять часов а");
+        assert_eq!(ru("в 11:30"), "в одиннадцать часов тридцать минут");
+        assert_eq!(ru("на 30 минут"), "на тридцать минут");
+        assert_eq!(ru("22 мин"), "двадцать две минуты");
+        assert_eq!(ru("2 тыс. рублей"), "две тысячи рублей");
+        assert_eq!(ru("стоит 100 руб."), "стоит сто рублей.");
+        assert_eq!(ru("3,5 км"), "три целых пять десятых километра");
+        assert_eq!(
+            ru("14.06.2023"),
+            "четырнадцатое июня две тысячи двадцать третьего года"
+        );
+        assert_eq!(ru("1-й"), "первый");
+        assert_eq!(ru("0-й"), "нулевой");
+        assert_eq!(ru("1000-й"), "тысячный");
+        assert_eq!(ru("2000-я"), "двухтысячная");
+        assert_eq!(ru("21000-й"), "двадцатиоднотысячный");
+        assert_eq!(ru("300000-й"), "трёхсоттысячный");
+        assert_eq!(ru("1000000-й"), "миллионный");
+        assert_eq!(ru("3021000-й"), "три миллиона двадцатиоднотысячный");
+        assert_eq!(ru("2000000000-й"), "двухмиллиардный");
+        assert_eq!(ru("т.е. всё"), "то есть всё");
+        assert_eq!(ru("яблоки и т.д. Потом"), "яблоки и так далее. Потом");
+        assert_eq!(ru("см. Приложение"), "смотри Приложение");
+    }
+
+    #[test]
+    fn test_unknown_language_unchanged() {
+        let text = "Um 10:00 kostet es 5 €";
+        assert_eq!(RuleBasedNormalizer::new().normalize(text, "de"), text);
+    }
+}
@@ -1,7 +1,14 @@
-use std::{env, error::Error, ffi, fmt, path::PathBuf};
+use std::{
+    env,
+    error::Error,
+    ffi::{self, CStr, CString},
+    fmt,
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
 
-use ffi_support::{rust_string_to_c, FfiStr};
-use once_cell::sync::Lazy;
+use once_cell::sync::{Lazy, OnceCell};
 
 use crate::espeakng;
 
@@ -15,113 +22,702 @@ const CLAUSE_INTONATION_QUESTION: i32 = 0x00002000;
 const CLAUSE_INTONATION_EXCLAMATION: i32 = 0x00003000;
 const CLAUSE_TYPE_SENTENCE: i32 = 0x00080000;
 
+/// Phoneme mode flag that makes eSpeak-ng use the separator character as a tie within multi-letter phonemes
+const PHONEME_MODE_TIE: u32 = 0x00000080;
+
 /// Name of the environment variable that points to the directory that contains `espeak-ng-data` directory
 /// only needed if `espeak-ng-data` directory is not in the expected location (i.e. eSpeak-ng is not installed system wide)
 const PIPER_ESPEAKNG_DATA_DIRECTORY: &str = "PIPER_ESPEAKNG_DATA_DIRECTORY";
 
 #[derive(Debug, Clone)]
-pub struct ESpeakError(pub String);
+pub struct ESpeakError {
+    pub message: String,
+    /// Error code returned by eSpeak-ng, if the failure came from an eSpeak-ng call
+    pub code: Option<i32>,
+}
+
+impl ESpeakError {
+    fn new(message: String, code: Option<i32>) -> Self {
+        Self { message, code }
+    }
+}
 
 impl Error for ESpeakError {}
 
 impl fmt::Display for ESpeakError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "eSpeak-ng Error :{}", self.0)
+        match self.code {
+            Some(code) => write!(f, "eSpeak-ng Error (code {}): {}", code, self.message),
+            None => write!(f, "eSpeak-ng Error: {}", self.message),
+        }
     }
 }
 
-static ESPEAKNG_INIT: Lazy<ESpeakResult<()>> = Lazy::new(|| {
-    let data_dir = match env::var(PIPER_ESPEAKNG_DATA_DIRECTORY) {
-        Ok(directory) => PathBuf::from(directory),
-        Err(_) => env::current_exe().unwrap().parent().unwrap().to_path_buf(),
+/// eSpeak-ng keeps the selected voice and the text being converted in global state,
+/// so voice selection and conversion must happen atomically with respect to other threads
+static ESPEAKNG_LOCK: Mutex<()> = Mutex::new(());
+
+/// Directory set through [`set_espeak_data_directory`], fixed once eSpeak-ng is initialized
+static ESPEAKNG_DATA_DIRECTORY: OnceCell<Option<PathBuf>> = OnceCell::new();
+
+static ESPEAKNG_INIT: Lazy<ESpeakResult<ESpeakInfo>> = Lazy::new(|| {
+    let candidates = match ESPEAKNG_DATA_DIRECTORY.get_or_init(|| None) {
+        Some(directory) => vec![directory.clone()],
+        None => data_directory_candidates(),
+    };
+    #[cfg(feature = "bundled-data")]
+    let (candidates, extraction_error) = {
+        let mut candidates = candidates;
+        let mut extraction_error = None;
+        if !candidates.iter().any(|dir| contains_espeak_data(dir)) {
+            // eSpeak-ng may still find the data in the location it was built for
+            match extract_bundled_espeak_data() {
+                Ok(directory) => candidates.push(directory),
+                Err(e) => extraction_error = Some(e),
+            }
+        }
+        (candidates, extraction_error)
     };
-    let es_data_path_ptr = if data_dir.join("espeak-ng-data").exists() {
-        rust_string_to_c(data_dir.display().to_string())
-    } else {
-        std::ptr::null()
+    #[cfg(not(feature = "bundled-data"))]
+    let extraction_error: Option<ESpeakError> = None;
+    // Without a candidate eSpeak-ng looks in the location it was built for
+    let data_dir = candidates.iter().find(|dir| contains_espeak_data(dir));
+    // eSpeak-ng copies the path during initialization, so it only has to outlive the call
+    let es_data_path = match data_dir {
+        Some(dir) => Some(to_c_string(dir.display().to_string())?),
+        None => None,
     };
-    unsafe {
-        let es_sample_rate = espeakng::espeak_Initialize(
+    let es_data_path_ptr = es_data_path
+        .as_ref()
+        .map_or(std::ptr::null(), |path| path.as_ptr());
+    let es_sample_rate = unsafe {
+        espeakng::espeak_Initialize(
             espeakng::espeak_AUDIO_OUTPUT_AUDIO_OUTPUT_RETRIEVAL,
             0,
             es_data_path_ptr,
             espeakng::espeakINITIALIZE_DONT_EXIT as i32,
+        )
+    };
+    let info = query_info();
+    // eSpeak-ng reports some missing data files only when a voice is loaded
+    if es_sample_rate <= 0 || !info.data_path.join("phontab").is_file() {
+        let searched = Vec::from_iter(
+            candidates
+                .iter()
+                .chain(Some(&info.data_path).filter(|path| !path.as_os_str().is_empty()))
+                .map(|dir| format!("`{}`", dir.display())),
         );
-        if es_sample_rate <= 0 {
-            Err(ESpeakError(format!(
-                "Failed to initialize eSpeak-ng. Try setting `{}` environment variable to the directory that contains the `espeak-ng-data` directory. Error code: `{}`",
-                PIPER_ESPEAKNG_DATA_DIRECTORY,
-                es_sample_rate
-            )))
-        } else {
-            Ok(())
-        }
+        let extraction_error =
+            extraction_error.map_or(String::new(), |e| format!(" {}.", e.message));
+        return Err(ESpeakError::new(
+            format!(
+                "Failed to initialize eSpeak-ng, no usable `espeak-ng-data` directory in {}.{} \
+                Call `set_espeak_data_directory` or set `{}` environment variable to the directory that contains the `espeak-ng-data` directory",
+                searched.join(", "),
+                extraction_error,
+                PIPER_ESPEAKNG_DATA_DIRECTORY
+            ),
+            Some(es_sample_rate).filter(|code| *code <= 0),
+        ));
     }
+    Ok(info)
 });
 
-pub fn text_to_phonemes(
+/// Directories searched for `espeak-ng-data` unless one was set explicitly
+fn data_directory_candidates() -> Vec<PathBuf> {
+    let mut candidates = Vec::new();
+    if let Ok(directory) = env::var(PIPER_ESPEAKNG_DATA_DIRECTORY) {
+        candidates.push(PathBuf::from(directory));
+    }
+    if let Some(exe_dir) = env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(Path::to_path_buf))
+    {
+        candidates.push(exe_dir);
+    }
+    candidates
+}
+
+fn contains_espeak_data(directory: &Path) -> bool {
+    directory.join("espeak-ng-data").is_dir()
+}
+
+/// Extracts the `espeak-ng-data` embedded by the `bundled-data` feature to the user's cache directory,
+/// or `PIPER_ESPEAKNG_CACHE_DIRECTORY` if set, returning the directory that contains it
+///
+/// Initializing eSpeak-ng falls back to this when none of the other data directory candidates has `espeak-ng-data`,
+/// the extracted data is reused by later runs.
+#[cfg(feature = "bundled-data")]
+pub fn extract_bundled_espeak_data() -> ESpeakResult<PathBuf> {
+    crate::bundled::extract_bundled_data().map_err(|e| {
+        ESpeakError::new(
+            format!("Failed to extract the bundled `espeak-ng-data`. {}", e),
+            None,
+        )
+    })
+}
+
+/// Version and data location of the eSpeak-ng library in use
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ESpeakInfo {
+    pub version: String,
+    /// The `espeak-ng-data` directory eSpeak-ng loads its voices and dictionaries from
+    pub data_path: PathBuf,
+}
+
+/// Gender declared by an eSpeak-ng voice file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ESpeakGender {
+    #[default]
+    Unknown,
+    Male,
+    Female,
+}
+
+impl ESpeakGender {
+    fn from_raw(gender: ffi::c_uchar) -> Self {
+        match gender {
+            1 => Self::Male,
+            2 => Self::Female,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+/// A voice found in eSpeak-ng's data directory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ESpeakVoice {
+    pub name: String,
+    /// Languages the voice speaks, in the order listed by the voice file
+    pub languages: Vec<String>,
+    pub gender: ESpeakGender,
+    /// Path of the voice file within `espeak-ng-data/voices`
+    pub identifier: String,
+}
+
+impl ESpeakVoice {
+    /// Whether eSpeak-ng selects this voice for `name`, the value of `espeak.voice` in voice configs
+    ///
+    /// Like `espeak_SetVoiceByName` this compares case insensitively against the name, the voice file
+    /// and the languages, ignoring a variant suffix such as `+f3`.
+    pub fn matches(&self, name: &str) -> bool {
+        let name = name.split('+').next().unwrap_or_default().trim();
+        let file_name = self.identifier.rsplit('/').next().unwrap_or_default();
+        [self.name.as_str(), self.identifier.as_str(), file_name]
+            .into_iter()
+            .chain(self.languages.iter().map(String::as_str))
+            .any(|candidate| candidate.eq_ignore_ascii_case(name))
+    }
+}
+
+/// Sets the directory that contains `espeak-ng-data`, taking precedence over `PIPER_ESPEAKNG_DATA_DIRECTORY`
+///
+/// Must be called before eSpeak-ng is first used, eSpeak-ng can not be re-initialized with another directory.
+pub fn set_espeak_data_directory(directory: impl Into<PathBuf>) -> ESpeakResult<()> {
+    let directory = directory.into();
+    if !contains_espeak_data(&directory) {
+        return Err(ESpeakError::new(
+            format!(
+                "`{}` does not contain an `espeak-ng-data` directory",
+                directory.display()
+            ),
+            None,
+        ));
+    }
+    match ESPEAKNG_DATA_DIRECTORY.set(Some(directory.clone())) {
+        Ok(()) => Ok(()),
+        Err(_) if ESPEAKNG_DATA_DIRECTORY.get() == Some(&Some(directory)) => Ok(()),
+        Err(_) => Err(ESpeakError::new(
+            "eSpeak-ng is already initialized, its data directory must be set before first use"
+                .to_string(),
+            None,
+        )),
+    }
+}
+
+/// Initializes eSpeak-ng if it is not yet, otherwise returns the result of the first initialization
+///
+/// Phonemization initializes eSpeak-ng on first use, calling this beforehand reports a missing data directory early.
+pub fn initialize_espeak() -> ESpeakResult<&'static ESpeakInfo> {
+    ESPEAKNG_INIT.as_ref().map_err(Clone::clone)
+}
+
+/// Lists the voices available in eSpeak-ng's data directory
+pub fn list_espeak_voices() -> ESpeakResult<Vec<ESpeakVoice>> {
+    initialize_espeak()?;
+    // The list lives in a buffer owned by eSpeak-ng that is rebuilt by the next call
+    let _guard = ESPEAKNG_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let mut voices = Vec::new();
+    unsafe {
+        let mut voice_ptr = espeakng::espeak_ListVoices(std::ptr::null_mut());
+        while !voice_ptr.is_null() && !(*voice_ptr).is_null() {
+            let voice = &**voice_ptr;
+            voices.push(ESpeakVoice {
+                name: c_str_to_string(voice.name),
+                languages: parse_voice_languages(voice.languages),
+                gender: ESpeakGender::from_raw(voice.gender),
+                identifier: c_str_to_string(voice.identifier),
+            });
+            voice_ptr = voice_ptr.add(1);
+        }
+    }
+    Ok(voices)
+}
+
+/// Looks up the voice eSpeak-ng selects for `name`, `None` if it is not in the data directory
+pub fn find_espeak_voice(name: &str) -> ESpeakResult<Option<ESpeakVoice>> {
+    Ok(list_espeak_voices()?
+        .into_iter()
+        .find(|voice| voice.matches(name)))
+}
+
+fn query_info() -> ESpeakInfo {
+    let mut data_path: *const ffi::c_char = std::ptr::null();
+    let version = unsafe { espeakng::espeak_Info(&mut data_path) };
+    ESpeakInfo {
+        version: c_str_to_string(version),
+        data_path: PathBuf::from(c_str_to_string(data_path)),
+    }
+}
+
+fn c_str_to_string(ptr: *const ffi::c_char) -> String {
+    if ptr.is_null() {
+        return String::new();
+    }
+    unsafe { CStr::from_ptr(ptr).to_string_lossy().into_owned() }
+}
+
+/// Reads eSpeak-ng's language list, a sequence of priority bytes each followed by a NUL terminated language
+/// and ended by a zero priority
+///
+/// # Safety
+/// `languages` must be null or point to a list in that format
+unsafe fn parse_voice_languages(mut languages: *const ffi::c_char) -> Vec<String> {
+    let mut parsed = Vec::new();
+    if languages.is_null() {
+        return parsed;
+    }
+    while *languages != 0 {
+        let language = CStr::from_ptr(languages.add(1));
+        parsed.push(language.to_string_lossy().into_owned());
+        languages = languages.add(language.to_bytes().len() + 2);
+    }
+    parsed
+}
+
+fn to_c_string(text: impl Into<Vec<u8>>) -> ESpeakResult<CString> {
+    CString::new(text).map_err(|_| {
+        ESpeakError::new(
+            "Text passed to eSpeak-ng must not contain NUL characters".to_string(),
+            None,
+        )
+    })
+}
+
+/// Phoneme alphabet of eSpeak-ng's output
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PhonemeEncoding {
+    /// International Phonetic Alphabet, the encoding piper voices are trained on
+    #[default]
+    Ipa,
+    /// eSpeak-ng's own ASCII phoneme names, as used in its `*_list` and `*_rules` files
+    Mnemonic,
+}
+
+/// Character eSpeak-ng puts between the phonemes of a word
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PhonemeSeparator {
+    /// Separates every phoneme, `t_ˈɛ_s_t`
+    Separator(char),
+    /// Only joins the characters of multi-letter phonemes, `t͡ʃ`
+    Tie(char),
+}
+
+/// Punctuation symbols commonly found in the `phoneme_id_map` of piper voices
+pub const DEFAULT_PRESERVED_PUNCTUATION: &str = ".,;:!?¡¿…—\"“”«»()";
+
+/// Which punctuation ends up in the phonemes of a clause
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum Punctuation {
+    /// One of `.`, `,`, `?` and `!` derived from the intonation eSpeak-ng reports for the clause
+    #[default]
+    Intonation,
+    /// The punctuation around the clause in the text, as far as it is one of these symbols.
+    /// Clauses without any of them fall back to the intonation's symbol if it is in the set.
+    ///
+    /// Only punctuation at the start and end of a clause is kept, eSpeak-ng reports where clauses end
+    /// but not where the symbols within them are, so the quotes in `he said "no" twice` are dropped.
+    /// eSpeak-ng's own punctuation setting (`espeakPUNCTUATION`) speaks the names of the symbols instead.
+    Preserve(String),
+}
+
+impl Punctuation {
+    /// Symbols that can end up in the phonemes
+    pub fn symbols(&self) -> &str {
+        match self {
+            Punctuation::Intonation => ".,?!",
+            Punctuation::Preserve(symbols) => symbols,
+        }
+    }
+    /// Adds the punctuation of a clause to its phonemes, `source` is the text eSpeak-ng read for the clause
+    fn punctuate(&self, mut phonemes: String, intonation: Option<char>, source: &str) -> String {
+        let symbols = match self {
+            Punctuation::Intonation => {
+                phonemes.extend(intonation);
+                return phonemes;
+            }
+            Punctuation::Preserve(symbols) => symbols,
+        };
+        let is_punctuation = |c: char| !c.is_alphanumeric() && !c.is_whitespace();
+        let is_kept = |c: &char| symbols.contains(*c);
+        let source = source.trim();
+        let without_leading = source.trim_start_matches(is_punctuation);
+        // A clause of punctuation only, such as a lone closing quote, counts as trailing punctuation
+        let (leading, rest) = match without_leading.is_empty() {
+            true => ("", source),
+            false => source.split_at(source.len() - without_leading.len()),
+        };
+        let trailing = &rest[rest.trim_end_matches(is_punctuation).len()..];
+
+        let mut punctuated = String::from_iter(leading.chars().filter(is_kept));
+        punctuated.push_str(&phonemes);
+        let kept_trailing = String::from_iter(trailing.chars().filter(is_kept));
+        match kept_trailing.is_empty() {
+            true => punctuated.extend(intonation.filter(is_kept)),
+            false => punctuated.push_str(&kept_trailing),
+        }
+        punctuated
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct PhonemeOptions {
+    pub encoding: PhonemeEncoding,
+    pub separator: Option<PhonemeSeparator>,
+    pub punctuation: Punctuation,
+}
+
+impl PhonemeOptions {
+    pub fn new(encoding: PhonemeEncoding) -> Self {
+        Self {
+            encoding,
+            separator: None,
+            punctuation: Punctuation::Intonation,
+        }
+    }
+    /// Keeps these punctuation symbols of the text instead of only the four eSpeak-ng's intonation maps to
+    pub fn with_punctuation(mut self, symbols: impl Into<String>) -> Self {
+        self.punctuation = Punctuation::Preserve(symbols.into());
+        self
+    }
+    pub fn with_separator(mut self, separator: char) -> Self {
+        self.separator = Some(PhonemeSeparator::Separator(separator));
+        self
+    }
+    pub fn with_tie(mut self, tie: char) -> Self {
+        self.separator = Some(PhonemeSeparator::Tie(tie));
+        self
+    }
+    /// The `phonememode` argument of `espeak_TextToPhonemes`
+    fn phoneme_mode(&self) -> ESpeakResult<ffi::c_int> {
+        let encoding = match self.encoding {
+            PhonemeEncoding::Ipa => espeakng::espeakINITIALIZE_PHONEME_IPA,
+            PhonemeEncoding::Mnemonic => 0,
+        };
+        let (c, tie) = match self.separator {
+            Some(PhonemeSeparator::Separator(c)) => (c, 0),
+            Some(PhonemeSeparator::Tie(c)) => (c, PHONEME_MODE_TIE),
+            None => return Ok(encoding as ffi::c_int),
+        };
+        // eSpeak-ng only reserves bits 8-23 of the phoneme mode for the separator
+        if (c as u32) > 0xFFFF {
+            return Err(ESpeakError::new(
+                format!(
+                    "Phoneme separator `{}` is outside the basic multilingual plane",
+                    c
+                ),
+                None,
+            ));
+        }
+        Ok((((c as u32) << 8u32) | tie | encoding) as ffi::c_int)
+    }
+}
+
+/// Where text is split into sentences besides eSpeak-ng's own sentence ends
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LineBreakMode {
+    /// Line breaks are treated like any other whitespace
+    #[default]
+    Ignore,
+    /// A blank line ends a sentence
+    Paragraph,
+    /// Every line break ends a sentence
+    Line,
+}
+
+/// Controls how phonemized text is split into the sentences that are synthesized one at a time
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SegmenterOptions {
+    pub line_breaks: LineBreakMode,
+    /// Longer sentences are split at clause breaks, and clauses that are still too long at word boundaries
+    pub max_sentence_phonemes: Option<usize>,
+}
+
+/// Phonemes of a sentence with the byte range of the text it was produced from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SentencePhonemes {
+    pub phonemes: String,
+    pub span: Range<usize>,
+}
+
+/// Phonemes of a clause as returned by one `espeak_TextToPhonemesWithTerminator` call
+struct Clause {
+    /// Words are separated by spaces
+    phonemes: String,
+    punctuation: Option<char>,
+    ends_sentence: bool,
+    /// Byte range of the clause in the converted text, including trailing punctuation and whitespace
+    span: Range<usize>,
+}
+
+fn text_to_clauses(
     text: &str,
     language: &str,
-    phoneme_separator: Option<char>,
-) -> ESpeakResult<Vec<String>> {
-    if let Err(ref e) = Lazy::force(&ESPEAKNG_INIT) {
-        return Err(e.clone());
-    }
+    options: &PhonemeOptions,
+) -> ESpeakResult<Vec<Clause>> {
+    initialize_espeak()?;
+    let phoneme_mode = options.phoneme_mode()?;
+    let language_c_str = to_c_string(language)?;
+    let text_c_str = to_c_string(text)?;
 
-    let set_voice_res = unsafe { espeakng::espeak_SetVoiceByName(rust_string_to_c(language)) };
+    // The guarded state is owned by eSpeak-ng, a panic while holding the lock can not leave it half updated
+    let _guard = ESPEAKNG_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+    let set_voice_res = unsafe { espeakng::espeak_SetVoiceByName(language_c_str.as_ptr()) };
     if set_voice_res != espeakng::espeak_ERROR_EE_OK {
-        return Err(ESpeakError(format!(
-            "Failed to set eSpeak-ng voice to: `{}` ",
-            language
-        )));
+        return Err(ESpeakError::new(
+            format!("Failed to set eSpeak-ng voice to: `{}`", language),
+            Some(set_voice_res),
+        ));
     }
 
-    let calculated_phoneme_mode = match phoneme_separator {
-        Some(c) => ((c as u32) << 8u32) | espeakng::espeakINITIALIZE_PHONEME_IPA,
-        None => espeakng::espeakINITIALIZE_PHONEME_IPA,
-    };
-    let phoneme_mode: i32 = calculated_phoneme_mode.try_into().unwrap();
-
-    let mut sent_phonemes = Vec::new();
-    let mut phonemes = String::new();
+    let mut clauses = Vec::new();
 
-    let mut text_c_char = rust_string_to_c(text) as *const ffi::c_char;
+    // eSpeak-ng advances this pointer through `text_c_str`, which stays alive until the loop is done
+    let mut text_c_char = text_c_str.as_ptr();
     let text_c_char_ptr = std::ptr::addr_of_mut!(text_c_char);
 
     let mut terminator: ffi::c_int = 0;
     let terminator_ptr: *mut ffi::c_int = &mut terminator;
 
+    // eSpeak-ng leaves the pointer at the start of the next clause, which gives the byte offset into the text
+    let text_offset = |ptr: *const ffi::c_char| -> usize {
+        if ptr.is_null() {
+            return text.len();
+        }
+        let mut offset = (ptr as usize)
+            .saturating_sub(text_c_str.as_ptr() as usize)
+            .min(text.len());
+        while !text.is_char_boundary(offset) {
+            offset += 1;
+        }
+        offset
+    };
+
     while !text_c_char.is_null() {
-        let ph_str = unsafe {
+        let clause_start = text_offset(text_c_char);
+        let mut phonemes = String::new();
+        unsafe {
+            // Points into a buffer owned by eSpeak-ng that is reused by the next call
             let res = espeakng::espeak_TextToPhonemesWithTerminator(
-                text_c_char_ptr,
-                espeakng::espeakCHARS_UTF8.try_into().unwrap(),
+                text_c_char_ptr.cast(),
+                espeakng::espeakCHARS_UTF8 as ffi::c_int,
                 phoneme_mode,
                 terminator_ptr,
             );
-            FfiStr::from_raw(res)
-        };
-        phonemes.push_str(&ph_str.into_string());
+            if !res.is_null() {
+                phonemes.push_str(&CStr::from_ptr(res).to_string_lossy());
+            }
+        }
 
         let intonation = terminator & 0x0000F000;
-        if intonation == CLAUSE_INTONATION_FULL_STOP {
-            phonemes.push('.');
+        let punctuation = if intonation == CLAUSE_INTONATION_FULL_STOP {
+            Some('.')
         } else if intonation == CLAUSE_INTONATION_COMMA {
-            phonemes.push(',');
+            Some(',')
         } else if intonation == CLAUSE_INTONATION_QUESTION {
-            phonemes.push('?');
+            Some('?')
         } else if intonation == CLAUSE_INTONATION_EXCLAMATION {
-            phonemes.push('!');
+            Some('!')
+        } else {
+            None
+        };
+
+        clauses.push(Clause {
+            phonemes,
+            punctuation,
+            ends_sentence: (terminator & CLAUSE_TYPE_SENTENCE) == CLAUSE_TYPE_SENTENCE,
+            span: clause_start..text_offset(text_c_char),
+        });
+    }
+    Ok(clauses)
+}
+
+/// Converts text into sentences of phonemes split as configured by `segmenter`, keeping track of their source text
+pub fn text_to_sentences(
+    text: &str,
+    language: &str,
+    options: &PhonemeOptions,
+    segmenter: SegmenterOptions,
+) -> ESpeakResult<Vec<SentencePhonemes>> {
+    let mut sentences = SentenceSplitter {
+        text,
+        max_phonemes: segmenter.max_sentence_phonemes,
+        sentences: Vec::new(),
+        current: None,
+    };
+    for block in split_blocks(text, segmenter.line_breaks) {
+        let block_start = block.start;
+        for clause in text_to_clauses(&text[block], language, options)? {
+            let span = clause.span.start + block_start..clause.span.end + block_start;
+            let phonemes = options.punctuation.punctuate(
+                clause.phonemes,
+                clause.punctuation,
+                &text[span.clone()],
+            );
+            sentences.push_clause(phonemes, span);
+            if clause.ends_sentence {
+                sentences.end_sentence();
+            }
+        }
+        sentences.end_sentence();
+    }
+    Ok(sentences.sentences)
+}
+
+/// Byte ranges of the parts of `text` that are phonemized separately
+fn split_blocks(text: &str, line_breaks: LineBreakMode) -> Vec<Range<usize>> {
+    if line_breaks == LineBreakMode::Ignore {
+        return vec![Range {
+            start: 0,
+            end: text.len(),
+        }];
+    }
+    let mut blocks: Vec<Range<usize>> = Vec::new();
+    let mut offset = 0;
+    let mut previous_blank = true;
+    for line in text.split_inclusive('\n') {
+        let line_range = offset..offset + line.len();
+        offset += line.len();
+        if line.trim().is_empty() {
+            previous_blank = true;
+            continue;
+        }
+        match blocks.last_mut() {
+            Some(block) if line_breaks == LineBreakMode::Paragraph && !previous_blank => {
+                block.end = line_range.end
+            }
+            _ => blocks.push(line_range),
+        }
+        previous_blank = false;
+    }
+    blocks
+}
+
+/// Groups clauses into sentences, starting a new sentence before a clause that would exceed the phoneme limit
+struct SentenceSplitter<'a> {
+    text: &'a str,
+    max_phonemes: Option<usize>,
+    sentences: Vec<SentencePhonemes>,
+    current: Option<SentencePhonemes>,
+}
+
+impl SentenceSplitter<'_> {
+    fn push_clause(&mut self, phonemes: String, span: Range<usize>) {
+        let pieces = match self.max_phonemes {
+            Some(max) if phonemes.chars().count() > max => split_at_words(&phonemes, max),
+            _ => vec![phonemes],
+        };
+        for piece in pieces {
+            let exceeds_limit = match (&self.current, self.max_phonemes) {
+                (Some(current), Some(max)) => {
+                    current.phonemes.chars().count() + piece.trim_end().chars().count() > max
+                }
+                _ => false,
+            };
+            if exceeds_limit {
+                self.end_sentence();
+            }
+            match self.current {
+                Some(ref mut current) => {
+                    current.phonemes.push_str(&piece);
+                    current.span.end = current.span.end.max(span.end);
+                }
+                None => {
+                    self.current = Some(SentencePhonemes {
+                        phonemes: piece,
+                        span: span.clone(),
+                    })
+                }
+            }
+        }
+    }
+    fn end_sentence(&mut self) {
+        let Some(mut sentence) = self.current.take() else {
+            return;
+        };
+        // Words of a clause split by `split_at_words` keep their separating space
+        sentence
+            .phonemes
+            .truncate(sentence.phonemes.trim_end().len());
+        if sentence.phonemes.is_empty() {
+            return;
+        }
+        // Clause spans include the whitespace eSpeak-ng skipped after them
+        let source = &self.text[sentence.span.clone()];
+        let leading = source.len() - source.trim_start().len();
+        let trailing = source.len() - source.trim_end().len();
+        if leading + trailing < source.len() {
+            sentence.span = sentence.span.start + leading..sentence.span.end - trailing;
         }
+        self.sentences.push(sentence);
+    }
+}
 
-        if (terminator & CLAUSE_TYPE_SENTENCE) == CLAUSE_TYPE_SENTENCE {
-            sent_phonemes.push(std::mem::take(&mut phonemes));
+/// Splits the phonemes of an overlong clause into pieces of whole words, each at most `max` phonemes if possible
+fn split_at_words(phonemes: &str, max: usize) -> Vec<String> {
+    let mut pieces: Vec<String> = Vec::new();
+    let mut piece = String::new();
+    for word in phonemes.split_inclusive(' ') {
+        if !piece.is_empty() && piece.chars().count() + word.trim_end().chars().count() > max {
+            pieces.push(std::mem::take(&mut piece));
         }
+        piece.push_str(word);
     }
-    if !phonemes.is_empty() {
-        sent_phonemes.push(std::mem::take(&mut phonemes));
+    if !piece.is_empty() {
+        pieces.push(piece);
     }
-    Ok(sent_phonemes)
+    pieces
+}
+
+/// Converts text into sentences of words, each word given as its phonemes without punctuation
+pub fn text_to_phoneme_words(
+    text: &str,
+    language: &str,
+    options: &PhonemeOptions,
+) -> ESpeakResult<Vec<Vec<String>>> {
+    let mut sentences = Vec::new();
+    let mut words = Vec::new();
+    for clause in text_to_clauses(text, language, options)? {
+        words.extend(clause.phonemes.split_whitespace().map(str::to_string));
+        if clause.ends_sentence {
+            sentences.push(std::mem::take(&mut words));
+        }
+    }
+    if !words.is_empty() {
+        sentences.push(words);
+    }
+    Ok(sentences)
 }
 
 // ==============================
@@ -130,6 +726,50 @@ pub fn text_to_phonemes(
 mod tests {
     use super::*;
 
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::cell::Cell;
+
+    fn text_to_phonemes(
+        text: &str,
+        language: &str,
+        options: &PhonemeOptions,
+    ) -> ESpeakResult<Vec<String>> {
+        let sentences = text_to_sentences(text, language, options, SegmenterOptions::default())?;
+        Ok(Vec::from_iter(
+            sentences.into_iter().map(|sentence| sentence.phonemes),
+        ))
+    }
+
+    /// Tracks the bytes allocated and not yet freed by the current thread,
+    /// so that tests running in parallel do not disturb each other's counts
+    struct CountingAllocator;
+
+    thread_local! {
+        static LIVE_BYTES: Cell<isize> = const { Cell::new(0) };
+    }
+
+    unsafe impl GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            LIVE_BYTES
+                .try_with(|b| b.set(b.get() + layout.size() as isize))
+                .ok();
+            System.alloc(layout)
+        }
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            LIVE_BYTES
+                .try_with(|b| b.set(b.get() - layout.size() as isize))
+                .ok();
+            System.dealloc(ptr, layout)
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+    fn live_bytes() -> isize {
+        LIVE_BYTES.with(|b| b.get())
+    }
+
     const TEXT_ALICE: &str =
         "Who are you? said the Caterpillar. Replied Alice , rather shyly, I hardly know, sir!";
 
@@ -137,14 +777,14 @@ mod tests {
     fn test_basic_en() -> ESpeakResult<()> {
         let text = "test";
         let expected = "tˈɛst.";
-        let phonemes = text_to_phonemes(text, "en-US", None)?.join("");
+        let phonemes = text_to_phonemes(text, "en-US", &PhonemeOptions::default())?.join("");
         assert_eq!(phonemes, expected);
         Ok(())
     }
 
     #[test]
     fn test_it_splits_sentences() -> ESpeakResult<()> {
-        let phonemes = text_to_phonemes(TEXT_ALICE, "en-US", None)?;
+        let phonemes = text_to_phonemes(TEXT_ALICE, "en-US", &PhonemeOptions::default())?;
         assert_eq!(phonemes.len(), 3);
         Ok(())
     }
@@ -153,14 +793,171 @@ mod tests {
     fn test_it_adds_phoneme_separator() -> ESpeakResult<()> {
         let text = "test";
         let expected = "t_ˈɛ_s_t.";
-        let phonemes = text_to_phonemes(text, "en-US", Some('_')).unwrap().join("");
+        let phonemes = text_to_phonemes(
+            text,
+            "en-US",
+            &PhonemeOptions::default().with_separator('_'),
+        )
+        .unwrap()
+        .join("");
         assert_eq!(phonemes, expected);
         Ok(())
     }
 
+    #[test]
+    fn test_mnemonic_encoding() -> ESpeakResult<()> {
+        let options = PhonemeOptions::new(PhonemeEncoding::Mnemonic);
+        let phonemes = text_to_phonemes("test", "en-US", &options)?.join("");
+        assert_eq!(phonemes, "t'Est.");
+        Ok(())
+    }
+
+    #[test]
+    fn test_rejects_separator_outside_bmp() {
+        let options = PhonemeOptions::default().with_tie('𝄞');
+        assert!(text_to_phonemes("test", "en-US", &options).is_err());
+    }
+
+    #[test]
+    fn test_groups_words() -> ESpeakResult<()> {
+        let sentences = text_to_phoneme_words(TEXT_ALICE, "en-US", &PhonemeOptions::default())?;
+        assert_eq!(sentences.len(), 3);
+        assert_eq!(sentences[0].len(), 3);
+        assert!(sentences
+            .iter()
+            .flatten()
+            .all(|word| !word.contains([' ', '.', ',', '?', '!'])));
+        Ok(())
+    }
+
+    #[test]
+    fn test_espeak_info_and_voices() -> ESpeakResult<()> {
+        let info = initialize_espeak()?;
+        assert!(!info.version.is_empty());
+        assert!(info.data_path.ends_with("espeak-ng-data"));
+        let voices = list_espeak_voices()?;
+        assert!(voices
+            .iter()
+            .any(|voice| voice.languages.iter().any(|language| language == "en-us")));
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_espeak_voice() -> ESpeakResult<()> {
+        let voice = find_espeak_voice("en-US")?.expect("en-us voice");
+        assert_eq!(voice.identifier, "gmw/en-US");
+        assert!(find_espeak_voice("ru+f3")?.is_some());
+        assert!(find_espeak_voice("en-uss")?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_data_directory_must_contain_data() {
+        let error =
+            set_espeak_data_directory(env::temp_dir().join("no-such-directory")).unwrap_err();
+        assert!(error.message.contains("espeak-ng-data"));
+    }
+
+    #[test]
+    fn test_sentence_spans() -> ESpeakResult<()> {
+        let sentences = text_to_sentences(
+            TEXT_ALICE,
+            "en-US",
+            &PhonemeOptions::default(),
+            SegmenterOptions::default(),
+        )?;
+        let spans = Vec::from_iter(sentences.iter().map(|s| &TEXT_ALICE[s.span.clone()]));
+        assert_eq!(
+            spans,
+            [
+                "Who are you?",
+                "said the Caterpillar.",
+                "Replied Alice , rather shyly, I hardly know, sir!"
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_splits_on_line_breaks() -> ESpeakResult<()> {
+        let text = "first line\nsecond line\n\nnext paragraph";
+        let split = |line_breaks| {
+            let segmenter = SegmenterOptions {
+                line_breaks,
+                max_sentence_phonemes: None,
+            };
+            text_to_sentences(text, "en-US", &PhonemeOptions::default(), segmenter)
+                .map(|sentences| Vec::from_iter(sentences.into_iter().map(|s| &text[s.span])))
+        };
+        assert_eq!(split(LineBreakMode::Ignore)?, [text]);
+        assert_eq!(
+            split(LineBreakMode::Paragraph)?,
+            ["first line\nsecond line", "next paragraph"]
+        );
+        assert_eq!(
+            split(LineBreakMode::Line)?,
+            ["first line", "second line", "next paragraph"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_caps_sentence_length() -> ESpeakResult<()> {
+        let text = "one two three, four five six, seven eight nine ten eleven twelve.";
+        let segmenter = SegmenterOptions {
+            line_breaks: LineBreakMode::Ignore,
+            max_sentence_phonemes: Some(20),
+        };
+        let sentences = text_to_sentences(text, "en-US", &PhonemeOptions::default(), segmenter)?;
+        assert!(sentences.len() > 2);
+        for sentence in sentences.iter() {
+            assert!(
+                sentence.phonemes.chars().count() <= 20 || !sentence.phonemes.trim().contains(' ')
+            );
+        }
+        // Clauses that fit stay whole, with their text span
+        assert!(text[sentences[0].span.clone()].starts_with("one two three"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_punctuate_clause() {
+        let preserve = Punctuation::Preserve(DEFAULT_PRESERVED_PUNCTUATION.to_string());
+        let punctuate = |punctuation: &Punctuation, source: &str, intonation: Option<char>| {
+            punctuation.punctuate("wɜːd".to_string(), intonation, source)
+        };
+        assert_eq!(punctuate(&preserve, "«word;» ", Some(',')), "«wɜːd;»");
+        assert_eq!(punctuate(&preserve, "word… ", Some('.')), "wɜːd…");
+        // Symbols outside the set are dropped, the intonation fills in when nothing is left
+        assert_eq!(punctuate(&preserve, "word* ", Some('.')), "wɜːd.");
+        assert_eq!(punctuate(&preserve, "\" ", None), "wɜːd\"");
+        // Punctuation within the clause is not kept
+        assert_eq!(
+            punctuate(&preserve, "he said \"no\" twice, ", Some(',')),
+            "wɜːd,"
+        );
+        assert_eq!(
+            punctuate(&Punctuation::Intonation, "word; ", Some(',')),
+            "wɜːd,"
+        );
+    }
+
+    #[test]
+    fn test_preserves_punctuation() -> ESpeakResult<()> {
+        let text = "Wait; \"what is it?\" she asked: nothing!";
+        let options = PhonemeOptions::default().with_punctuation(DEFAULT_PRESERVED_PUNCTUATION);
+        let phonemes = text_to_phonemes(text, "en-US", &options)?.join("");
+        for symbol in [';', '"', '?', ':', '!'] {
+            assert!(phonemes.contains(symbol), "{} in {}", symbol, phonemes);
+        }
+        let phonemes = text_to_phonemes(text, "en-US", &PhonemeOptions::default())?.join("");
+        assert!(!phonemes.contains([';', ':']), "{}", phonemes);
+        Ok(())
+    }
+
     #[test]
     fn test_it_preserves_clause_breakers() -> ESpeakResult<()> {
-        let phonemes = text_to_phonemes(TEXT_ALICE, "en-US", None)?.join("");
+        let phonemes = text_to_phonemes(TEXT_ALICE, "en-US", &PhonemeOptions::default())?.join("");
         let clause_breakers = ['.', ',', '?', '!'];
         for c in clause_breakers {
             assert!(phonemes.contains(c), "Clause breaker `{}` not preserved", c);
@@ -168,11 +965,59 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_concurrent_languages() -> ESpeakResult<()> {
+        let samples = [
+            ("en-US", TEXT_ALICE),
+            ("ar", "مَرْحَبَاً بِكَ أَيُّهَا الْرَّجُلْ"),
+            ("de", "Ich bin so wild nach deinem Erdbeermund."),
+            ("ru", "Важный день. Через месяц у Ани важный день."),
+        ];
+        let expected = samples
+            .iter()
+            .map(|(language, text)| text_to_phonemes(text, language, &PhonemeOptions::default()))
+            .collect::<ESpeakResult<Vec<_>>>()?;
+        let handles = Vec::from_iter((0..8).map(|i| {
+            let (language, text) = samples[i % samples.len()];
+            let expected = expected[i % samples.len()].clone();
+            std::thread::spawn(move || {
+                for _ in 0..25 {
+                    assert_eq!(
+                        text_to_phonemes(text, language, &PhonemeOptions::default()).unwrap(),
+                        expected
+                    );
+                }
+            })
+        }));
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_no_memory_growth() -> ESpeakResult<()> {
+        // Initializes eSpeak-ng and any lazily created state before measuring
+        text_to_phonemes(TEXT_ALICE, "en-US", &PhonemeOptions::default())?;
+        let before = live_bytes();
+        for _ in 0..500 {
+            let phonemes = text_to_phonemes(TEXT_ALICE, "en-US", &PhonemeOptions::default())?;
+            assert_eq!(phonemes.len(), 3);
+        }
+        assert_eq!(live_bytes() - before, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_rejects_nul() {
+        assert!(text_to_phonemes("te\0st", "en-US", &PhonemeOptions::default()).is_err());
+    }
+
     #[test]
     fn test_arabic() -> ESpeakResult<()> {
         let text = "مَرْحَبَاً بِكَ أَيُّهَا الْرَّجُلْ";
         let expected = "mˈarħabˌaː bikˌa ʔaˈiːuhˌaː alrrˈadʒul.";
-        let phonemes = text_to_phonemes(text, "ar", None)?.join("");
+        let phonemes = text_to_phonemes(text, "ar", &PhonemeOptions::default())?.join("");
         assert_eq!(phonemes, expected);
         Ok(())
     }
@@ -0,0 +1,274 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use crate::core::{Phonemes, PiperResult, TextSpan};
+use crate::lru::SharedLruCache;
+use crate::phonemize::{text_to_phoneme_words, text_to_sentences};
+
+#[cfg(feature = "bundled-data")]
+pub use crate::phonemize::extract_bundled_espeak_data;
+pub use crate::phonemize::{
+    find_espeak_voice, initialize_espeak, list_espeak_voices, set_espeak_data_directory,
+    ESpeakGender, ESpeakInfo, ESpeakVoice, LineBreakMode, PhonemeEncoding, PhonemeOptions,
+    PhonemeSeparator, Punctuation, SegmenterOptions, SentencePhonemes,
+    DEFAULT_PRESERVED_PUNCTUATION,
+};
+
+//----------------------------------------------------------------
+
+/// Converts text in the given language into sentences of phonemes
+///
+/// The language is the eSpeak-ng voice name from the model config (e.g. `en-us`),
+/// implementations are free to interpret it as they see fit.
+pub trait Phonemizer: Send + Sync {
+    fn phonemize(&self, text: &str, language: &str) -> PiperResult<Phonemes>;
+}
+
+/// Phonemizer backed by eSpeak-ng, the default for piper voices
+///
+/// Voices expect the default options (IPA without separators), the other encodings and separators
+/// are meant for inspecting pronunciations and building lexicons.
+#[derive(Debug, Clone, Default)]
+pub struct ESpeakPhonemizer {
+    options: PhonemeOptions,
+    segmenter: SegmenterOptions,
+}
+
+impl ESpeakPhonemizer {
+    pub fn new(options: PhonemeOptions) -> Self {
+        Self {
+            options,
+            segmenter: SegmenterOptions::default(),
+        }
+    }
+    /// Splits sentences at line breaks or caps their length instead of relying on eSpeak-ng's sentence ends only
+    pub fn with_segmenter(mut self, segmenter: SegmenterOptions) -> Self {
+        self.segmenter = segmenter;
+        self
+    }
+    pub fn options(&self) -> &PhonemeOptions {
+        &self.options
+    }
+    pub fn segmenter(&self) -> SegmenterOptions {
+        self.segmenter
+    }
+    /// Phonemizes text into sentences along with the byte range of the text each one was produced from
+    pub fn phonemize_sentences(
+        &self,
+        text: &str,
+        language: &str,
+    ) -> PiperResult<Vec<SentencePhonemes>> {
+        Ok(text_to_sentences(
+            text,
+            language,
+            &self.options,
+            self.segmenter,
+        )?)
+    }
+    /// Phonemizes text into sentences of words, punctuation is left out
+    pub fn phonemize_words(&self, text: &str, language: &str) -> PiperResult<Vec<Vec<String>>> {
+        Ok(text_to_phoneme_words(text, language, &self.options)?)
+    }
+}
+
+impl Phonemizer for ESpeakPhonemizer {
+    fn phonemize(&self, text: &str, language: &str) -> PiperResult<Phonemes> {
+        let sentences = self.phonemize_sentences(text, language)?;
+        Ok(Phonemes::from_iter(sentences.into_iter().map(|sentence| {
+            (sentence.phonemes, Some(TextSpan::new(text, sentence.span)))
+        })))
+    }
+}
+
+/// Passes text through unchanged as a single sentence,
+/// used by voices with `phoneme_type` set to `text`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PassthroughPhonemizer;
+
+impl Phonemizer for PassthroughPhonemizer {
+    fn phonemize(&self, text: &str, _language: &str) -> PiperResult<Phonemes> {
+        let mut phonemes = Phonemes::new();
+        phonemes.push(text.to_string(), Some(TextSpan::new(text, 0..text.len())));
+        Ok(phonemes)
+    }
+}
+
+/// Dispatches to a phonemizer registered for the requested language, falling back to a default one
+pub struct PerLanguagePhonemizer {
+    default: Arc<dyn Phonemizer>,
+    phonemizers: HashMap<String, Arc<dyn Phonemizer>>,
+}
+
+impl PerLanguagePhonemizer {
+    pub fn new(default: Arc<dyn Phonemizer>) -> Self {
+        Self {
+            default,
+            phonemizers: HashMap::new(),
+        }
+    }
+    /// Languages are matched case insensitively
+    pub fn with_language(mut self, language: &str, phonemizer: Arc<dyn Phonemizer>) -> Self {
+        self.phonemizers.insert(language.to_lowercase(), phonemizer);
+        self
+    }
+}
+
+impl Phonemizer for PerLanguagePhonemizer {
+    fn phonemize(&self, text: &str, language: &str) -> PiperResult<Phonemes> {
+        match self.phonemizers.get(&language.to_lowercase()) {
+            Some(phonemizer) => phonemizer.phonemize(text, language),
+            None => self.default.phonemize(text, language),
+        }
+    }
+}
+
+/// Lookups and size of a [`CachingPhonemizer`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct PhonemeCacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    /// Texts currently cached
+    pub entries: usize,
+    pub capacity: usize,
+}
+
+impl PhonemeCacheStats {
+    /// Share of lookups answered from the cache, 0 before the first lookup
+    pub fn hit_rate(&self) -> f64 {
+        match self.hits + self.misses {
+            0 => 0.0,
+            lookups => self.hits as f64 / lookups as f64,
+        }
+    }
+}
+
+/// Remembers the phonemes of the most recently phonemized texts, for voices that speak the same prompts over and over
+///
+/// Entries are keyed by language and the whole text given to the phonemizer, which synthesizers have already normalized,
+/// not by its sentences: a prompt only hits when it is phonemized on its own, not as part of a longer text.
+/// Splitting the text into sentences first would change how eSpeak-ng reads abbreviations it was left to handle.
+/// Failed phonemizations are not cached.
+pub struct CachingPhonemizer {
+    inner: Arc<dyn Phonemizer>,
+    cache: SharedLruCache<(String, String), Phonemes>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl CachingPhonemizer {
+    /// Keeps the phonemes of up to `capacity` texts
+    pub fn new(inner: Arc<dyn Phonemizer>, capacity: usize) -> Self {
+        Self {
+            inner,
+            cache: SharedLruCache::new(capacity),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+    /// Phonemizes phrases ahead of time without counting them as lookups
+    ///
+    /// Phrases must be normalized the way synthesized text is, see
+    /// [`PiperSpeechSynthesizer::normalize_text`](crate::synth::PiperSpeechSynthesizer::normalize_text).
+    pub fn prewarm<I>(&self, phrases: I, language: &str) -> PiperResult<()>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        for phrase in phrases {
+            let phrase = phrase.as_ref();
+            let phonemes = self.inner.phonemize(phrase, language)?;
+            self.cache
+                .lock()
+                .insert(cache_key(phrase, language), phonemes);
+        }
+        Ok(())
+    }
+    pub fn stats(&self) -> PhonemeCacheStats {
+        let cache = self.cache.lock();
+        PhonemeCacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            entries: cache.len(),
+            capacity: cache.capacity(),
+        }
+    }
+    /// Drops the cached phonemes, the statistics are kept
+    pub fn clear(&self) {
+        self.cache.lock().clear();
+    }
+}
+
+impl Phonemizer for CachingPhonemizer {
+    fn phonemize(&self, text: &str, language: &str) -> PiperResult<Phonemes> {
+        let key = cache_key(text, language);
+        if let Some(phonemes) = self.cache.lock().get(&key) {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(phonemes.clone());
+        }
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        // Not holding the lock, so that other texts are phonemized in parallel
+        let phonemes = self.inner.phonemize(text, language)?;
+        self.cache.lock().insert(key, phonemes.clone());
+        Ok(phonemes)
+    }
+}
+
+/// Languages are matched case insensitively, like [`PerLanguagePhonemizer`] does
+fn cache_key(text: &str, language: &str) -> (String, String) {
+    (language.to_lowercase(), text.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct FixedPhonemizer(&'static str);
+
+    impl Phonemizer for FixedPhonemizer {
+        fn phonemize(&self, _text: &str, _language: &str) -> PiperResult<Phonemes> {
+            Ok(Phonemes::from(vec![self.0.to_string()]))
+        }
+    }
+
+    /// Counts the texts it phonemized
+    #[derive(Default)]
+    struct CountingPhonemizer(AtomicU64);
+
+    impl Phonemizer for CountingPhonemizer {
+        fn phonemize(&self, text: &str, language: &str) -> PiperResult<Phonemes> {
+            self.0.fetch_add(1, Ordering::Relaxed);
+            PassthroughPhonemizer.phonemize(text, language)
+        }
+    }
+
+    #[test]
+    fn test_caches_phonemes() -> PiperResult<()> {
+        let counting = Arc::new(CountingPhonemizer::default());
+        let phonemizer = CachingPhonemizer::new(counting.clone(), 2);
+        phonemizer.prewarm(["one", "two"], "en-us")?;
+        assert_eq!(phonemizer.phonemize("one", "EN-US")?.to_vec(), ["one"]);
+        assert_eq!(phonemizer.phonemize("two", "en-us")?.to_vec(), ["two"]);
+        // Evicts `one`, used less recently than `two`
+        phonemizer.phonemize("three", "en-us")?;
+        phonemizer.phonemize("two", "en-us")?;
+        phonemizer.phonemize("one", "en-us")?;
+        assert_eq!(counting.0.load(Ordering::Relaxed), 4);
+        let stats = phonemizer.stats();
+        assert_eq!((stats.hits, stats.misses, stats.entries), (3, 2, 2));
+        assert_eq!(stats.hit_rate(), 0.6);
+        // Keys are whole texts, cached ones inside a longer text are phonemized again
+        phonemizer.phonemize("one. two", "en-us")?;
+        assert_eq!(phonemizer.stats().misses, 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_per_language_dispatch() -> PiperResult<()> {
+        let phonemizer = PerLanguagePhonemizer::new(Arc::new(PassthroughPhonemizer))
+            .with_language("en-US", Arc::new(FixedPhonemizer("ɪŋɡlɪʃ")));
+        assert_eq!(phonemizer.phonemize("x", "en-us")?.to_vec(), ["ɪŋɡlɪʃ"]);
+        assert_eq!(phonemizer.phonemize("x", "de")?.to_vec(), ["x"]);
+        Ok(())
+    }
+}
@@ -0,0 +1,219 @@
+use crate::core::{PiperError, PiperResult, TextSegment};
+
+//----------------------------------------------------------------
+
+/// Attribute that sets the language of an element and its content
+const XML_LANG: &str = "xml:lang";
+
+/// Splits an SSML document into text segments tagged with the language in effect, as set by `xml:lang`
+///
+/// Only language switching is interpreted, the text content of other elements (`<prosody>`, `<emphasis>`, ...)
+/// is kept and `<break>` is read as a pause between words. Text outside any `xml:lang` has no language.
+pub fn parse_ssml(ssml: &str) -> PiperResult<Vec<TextSegment>> {
+    let mut segments: Vec<TextSegment> = Vec::new();
+    // Open elements with the language they set, if any
+    let mut open_elements: Vec<(String, Option<String>)> = Vec::new();
+    let mut rest = ssml;
+    while !rest.is_empty() {
+        let Some(tag_start) = rest.find('<') else {
+            push_text(&mut segments, &decode_entities(rest)?, &open_elements);
+            break;
+        };
+        push_text(
+            &mut segments,
+            &decode_entities(&rest[..tag_start])?,
+            &open_elements,
+        );
+        rest = &rest[tag_start..];
+
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            rest = skip_past(comment, "-->")?;
+            continue;
+        }
+        if let Some(declaration) = rest.strip_prefix("<?").or_else(|| rest.strip_prefix("<!")) {
+            rest = skip_past(declaration, ">")?;
+            continue;
+        }
+        let tag_end = rest
+            .find('>')
+            .ok_or_else(|| ssml_error("Unterminated tag".to_string()))?;
+        let tag = &rest[1..tag_end];
+        rest = &rest[tag_end + 1..];
+
+        if let Some(name) = tag.strip_prefix('/') {
+            let name = name.trim();
+            match open_elements.pop() {
+                Some((open_name, _)) if open_name == name => (),
+                Some((open_name, _)) => {
+                    return Err(ssml_error(format!(
+                        "Closing tag `{}` does not match `{}`",
+                        name, open_name
+                    )))
+                }
+                None => return Err(ssml_error(format!("Unexpected closing tag `{}`", name))),
+            }
+            continue;
+        }
+        let (tag, self_closing) = match tag.strip_suffix('/') {
+            Some(tag) => (tag, true),
+            None => (tag, false),
+        };
+        let (name, attributes) = tag
+            .trim()
+            .split_once(char::is_whitespace)
+            .unwrap_or((tag.trim(), ""));
+        if name.is_empty() {
+            return Err(ssml_error("Tag without a name".to_string()));
+        }
+        if name == "break" {
+            push_text(&mut segments, " ", &open_elements);
+        }
+        if !self_closing {
+            let language = parse_attributes(attributes)?
+                .into_iter()
+                .find(|(key, _)| *key == XML_LANG)
+                .map(|(_, value)| value);
+            open_elements.push((name.to_string(), language));
+        }
+    }
+    if let Some((name, _)) = open_elements.last() {
+        return Err(ssml_error(format!("Element `{}` is not closed", name)));
+    }
+    segments.retain(|segment| !segment.text.trim().is_empty());
+    Ok(segments)
+}
+
+fn ssml_error(message: String) -> PiperError {
+    PiperError::InvalidSsml(message)
+}
+
+fn skip_past<'a>(text: &'a str, terminator: &str) -> PiperResult<&'a str> {
+    match text.find(terminator) {
+        Some(i) => Ok(&text[i + terminator.len()..]),
+        None => Err(ssml_error(format!("Missing `{}`", terminator))),
+    }
+}
+
+/// Appends text to the last segment if it has the same language, otherwise starts a new segment
+fn push_text(
+    segments: &mut Vec<TextSegment>,
+    text: &str,
+    open_elements: &[(String, Option<String>)],
+) {
+    if text.is_empty() {
+        return;
+    }
+    let language = open_elements
+        .iter()
+        .rev()
+        .find_map(|(_, language)| language.clone());
+    match segments.last_mut() {
+        Some(last) if last.language == language => last.text.push_str(text),
+        _ => segments.push(TextSegment {
+            text: text.to_string(),
+            language,
+        }),
+    }
+}
+
+fn parse_attributes(attributes: &str) -> PiperResult<Vec<(&str, String)>> {
+    let mut parsed = Vec::new();
+    let mut rest = attributes.trim();
+    while !rest.is_empty() {
+        let (key, after_key) = rest
+            .split_once('=')
+            .ok_or_else(|| ssml_error(format!("Attribute without a value in `{}`", attributes)))?;
+        let after_key = after_key.trim_start();
+        let quote = after_key
+            .chars()
+            .next()
+            .filter(|c| *c == '"' || *c == '\'')
+            .ok_or_else(|| ssml_error(format!("Unquoted attribute value in `{}`", attributes)))?;
+        let value_end = after_key[1..].find(quote).ok_or_else(|| {
+            ssml_error(format!("Unterminated attribute value in `{}`", attributes))
+        })?;
+        parsed.push((key.trim(), decode_entities(&after_key[1..value_end + 1])?));
+        rest = after_key[value_end + 2..].trim_start();
+    }
+    Ok(parsed)
+}
+
+fn decode_entities(text: &str) -> PiperResult<String> {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find(';')
+            .ok_or_else(|| ssml_error("Unterminated entity".to_string()))?;
+        let entity = &rest[start + 1..start + end];
+        let c = match entity {
+            "amp" => '&',
+            "lt" => '<',
+            "gt" => '>',
+            "quot" => '"',
+            "apos" => '\'',
+            _ => {
+                let code = match entity
+                    .strip_prefix("#x")
+                    .or_else(|| entity.strip_prefix("#X"))
+                {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                    None => entity.strip_prefix('#').and_then(|dec| dec.parse().ok()),
+                };
+                code.and_then(char::from_u32)
+                    .ok_or_else(|| ssml_error(format!("Unknown entity `&{};`", entity)))?
+            }
+        };
+        decoded.push(c);
+        rest = &rest[start + end + 1..];
+    }
+    decoded.push_str(rest);
+    Ok(decoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segment(text: &str, language: Option<&str>) -> TextSegment {
+        TextSegment {
+            text: text.to_string(),
+            language: language.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn test_language_switching() -> PiperResult<()> {
+        let ssml = r#"<?xml version="1.0"?>
+            <speak version="1.0" xml:lang="en-US">Hello <lang xml:lang='ru'>мир</lang>, <emphasis>again</emphasis>.</speak>"#;
+        let segments = parse_ssml(ssml)?;
+        assert_eq!(
+            segments,
+            [
+                segment("Hello ", Some("en-US")),
+                segment("мир", Some("ru")),
+                segment(", again.", Some("en-US")),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_entities_and_breaks() -> PiperResult<()> {
+        let segments = parse_ssml("<speak>Tom &amp; Jerry<break time=\"1s\"/>&#x41;</speak>")?;
+        assert_eq!(segments, [segment("Tom & Jerry A", None)]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_malformed_markup() {
+        assert_eq!(
+            parse_ssml("<speak>text").map_err(|e| e.kind()),
+            Err(crate::core::PiperErrorKind::InvalidInput)
+        );
+        assert!(parse_ssml("<speak><s>text</speak></s>").is_err());
+        assert!(parse_ssml("<speak xml:lang=en>text</speak>").is_err());
+        assert!(parse_ssml("<speak>&nbsp;</speak>").is_err());
+    }
+}
@@ -1,13 +1,22 @@
 use std::collections::vec_deque::VecDeque;
+use std::collections::HashMap;
 use std::io::Cursor;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
 
-use once_cell::sync::{Lazy, OnceCell};
+use once_cell::sync::Lazy;
 
 use rayon::prelude::*;
 use rayon::{ThreadPool, ThreadPoolBuilder};
 
-use crate::core::{PiperError, PiperModel, PiperResult, PiperWaveResult, PiperWaveSamples};
+use crate::core::{
+    Phonemes, PiperError, PiperModel, PiperResult, PiperWaveResult, PiperWaveSamples, TextSegment,
+    TextSpan,
+};
+#[cfg(feature = "langid")]
+use crate::langid::{self, LanguageDetector};
+use crate::normalize::{NormalizedText, RuleBasedNormalizer, TextNormalizer};
+use crate::ssml;
 use crate::wave_writer;
 
 //----------------------------------------------------------------
@@ -32,45 +41,212 @@ static SYNTHESIS_THREAD_POOL: Lazy<ThreadPool> = Lazy::new(|| {
         .unwrap()
 });
 
-pub struct PiperSpeechSynthesizer(Arc<dyn PiperModel + Sync + Send>);
+/// Token used to cancel in-flight synthesis
+///
+/// Clones share the same state, so a token can be handed to a synthesis call and cancelled from another thread,
+/// e.g. when the client disconnects. Sentences that have not started yet are skipped and the stream ends with
+/// [`PiperError::Cancelled`].
+#[derive(Clone, Default)]
+pub struct CancellationToken(Arc<CancellationState>);
+
+#[derive(Default)]
+struct CancellationState {
+    cancelled: AtomicBool,
+    parent: Option<CancellationToken>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn cancel(&self) {
+        self.0.cancelled.store(true, Ordering::Release);
+    }
+    pub fn is_cancelled(&self) -> bool {
+        self.0.cancelled.load(Ordering::Acquire)
+            || self.0.parent.as_ref().is_some_and(|p| p.is_cancelled())
+    }
+    /// Creates a token that is cancelled together with this one, but can also be cancelled on its own
+    pub fn child_token(&self) -> Self {
+        Self(Arc::new(CancellationState {
+            cancelled: AtomicBool::new(false),
+            parent: Some(self.clone()),
+        }))
+    }
+}
+
+pub struct PiperSpeechSynthesizer {
+    model: Arc<dyn PiperModel + Sync + Send>,
+    normalizer: Option<Arc<dyn TextNormalizer>>,
+    /// Voices that text segments in other languages are routed to, keyed by lowercase language
+    voices: HashMap<String, Arc<dyn PiperModel + Sync + Send>>,
+    #[cfg(feature = "langid")]
+    language_detector: LanguageDetector,
+}
 
 impl PiperSpeechSynthesizer {
+    /// Creates a synthesizer that normalizes text with the built-in [`RuleBasedNormalizer`]
     pub fn new(model: Arc<dyn PiperModel + Sync + Send>) -> PiperResult<Self> {
-        Ok(Self(model))
+        Ok(Self {
+            model,
+            normalizer: Some(Arc::new(RuleBasedNormalizer::new())),
+            voices: HashMap::new(),
+            #[cfg(feature = "langid")]
+            language_detector: LanguageDetector::new(),
+        })
+    }
+    /// Routes text segments in `language` to another model, either a full language (`en-gb`) or a base language (`en`)
+    ///
+    /// Segments in languages without a registered voice are phonemized for their language and spoken by the default model.
+    pub fn with_voice(mut self, language: &str, model: Arc<dyn PiperModel + Sync + Send>) -> Self {
+        self.voices.insert(language.to_lowercase(), model);
+        self
+    }
+    /// Replaces the normalizer applied to the text before phonemization
+    pub fn with_normalizer(mut self, normalizer: Arc<dyn TextNormalizer>) -> Self {
+        self.normalizer = Some(normalizer);
+        self
+    }
+    /// Passes the text to the phonemizer as is
+    pub fn without_normalizer(mut self) -> Self {
+        self.normalizer = None;
+        self
+    }
+    /// Normalizes `text` for the model's language, models that don't report a language get the text unchanged
+    pub fn normalize_text(&self, text: String) -> String {
+        self.normalize_text_for(&text, self.model.language()).text
+    }
+    /// Identifies the default model's voice and synthesis parameters, see [`PiperModel::synthesis_fingerprint`]
+    pub fn synthesis_fingerprint(&self) -> Option<String> {
+        self.model.synthesis_fingerprint()
+    }
+    /// A synthesizer speaking with [`PiperModel::without_noise`] of the default model and the voices of other languages
+    pub fn without_noise(&self) -> Self {
+        let without_noise = |model: &Arc<dyn PiperModel + Sync + Send>| {
+            model.without_noise().unwrap_or_else(|| model.clone())
+        };
+        Self {
+            model: without_noise(&self.model),
+            normalizer: self.normalizer.clone(),
+            voices: HashMap::from_iter(
+                self.voices
+                    .iter()
+                    .map(|(language, model)| (language.clone(), without_noise(model))),
+            ),
+            #[cfg(feature = "langid")]
+            language_detector: self.language_detector.clone(),
+        }
+    }
+    fn normalize_text_for(&self, text: &str, language: Option<String>) -> NormalizedText {
+        match (&self.normalizer, language) {
+            (Some(normalizer), Some(language)) => {
+                normalizer.normalize_with_offsets(text, &language)
+            }
+            _ => NormalizedText::unchanged(text),
+        }
+    }
+    fn voice_for(&self, language: &str) -> Option<&Arc<dyn PiperModel + Sync + Send>> {
+        let language = language.to_lowercase();
+        self.voices.get(&language).or_else(|| {
+            let base_language = language.split(['-', '_']).next()?;
+            self.voices.get(base_language)
+        })
     }
 
-    fn create_synthesis_task_provider(&self, text: String) -> SpeechSynthesisTaskProvider {
+    fn create_synthesis_task_provider(
+        &self,
+        text: String,
+        cancel_token: &CancellationToken,
+    ) -> SpeechSynthesisTaskProvider {
         SpeechSynthesisTaskProvider {
-            model: Arc::clone(&self.0),
+            model: Arc::clone(&self.model),
+            normalized: self.normalize_text_for(&text, self.model.language()),
             text,
+            language: None,
+            cancel_token: cancel_token.child_token(),
+        }
+    }
+    /// Picks the model of a segment, only the default model is asked to phonemize in a foreign language
+    fn create_segment_task_provider(
+        &self,
+        segment: TextSegment,
+        cancel_token: &CancellationToken,
+    ) -> SpeechSynthesisTaskProvider {
+        let routed_model = segment
+            .language
+            .as_deref()
+            .and_then(|language| self.voice_for(language));
+        let (model, language) = match routed_model {
+            Some(model) => (Arc::clone(model), None),
+            None => (Arc::clone(&self.model), segment.language),
+        };
+        let text_language = language.clone().or_else(|| model.language());
+        SpeechSynthesisTaskProvider {
+            normalized: self.normalize_text_for(&segment.text, text_language),
+            text: segment.text,
+            model,
+            language,
+            cancel_token: cancel_token.child_token(),
         }
     }
 
     pub fn synthesize_lazy(&self, text: String) -> PiperResult<PiperSpeechStreamLazy> {
-        PiperSpeechStreamLazy::new(self.create_synthesis_task_provider(text))
+        self.synthesize_lazy_with_cancellation(text, &CancellationToken::new())
+    }
+    pub fn synthesize_lazy_with_cancellation(
+        &self,
+        text: String,
+        cancel_token: &CancellationToken,
+    ) -> PiperResult<PiperSpeechStreamLazy> {
+        PiperSpeechStreamLazy::new(self.create_synthesis_task_provider(text, cancel_token))
     }
     pub fn synthesize_parallel(&self, text: String) -> PiperResult<PiperSpeechStreamParallel> {
-        PiperSpeechStreamParallel::new(self.create_synthesis_task_provider(text))
+        self.synthesize_parallel_with_cancellation(text, &CancellationToken::new())
+    }
+    pub fn synthesize_parallel_with_cancellation(
+        &self,
+        text: String,
+        cancel_token: &CancellationToken,
+    ) -> PiperResult<PiperSpeechStreamParallel> {
+        PiperSpeechStreamParallel::new(self.create_synthesis_task_provider(text, cancel_token))
     }
     pub fn synthesize_batched(
         &self,
         text: String,
         batch_size: Option<usize>,
+    ) -> PiperResult<PiperSpeechStreamBatched> {
+        self.synthesize_batched_with_cancellation(text, batch_size, &CancellationToken::new())
+    }
+    pub fn synthesize_batched_with_cancellation(
+        &self,
+        text: String,
+        batch_size: Option<usize>,
+        cancel_token: &CancellationToken,
     ) -> PiperResult<PiperSpeechStreamBatched> {
         let mut batch_size = batch_size.unwrap_or(SPEECH_STREAM_BATCH_SIZE);
         if batch_size == 0 {
             batch_size = SPEECH_STREAM_BATCH_SIZE;
         }
-        PiperSpeechStreamBatched::new(self.create_synthesis_task_provider(text), batch_size)
+        PiperSpeechStreamBatched::new(
+            self.create_synthesis_task_provider(text, cancel_token),
+            batch_size,
+        )
     }
 
     pub fn synthesize_to_samples(&self, text: String) -> PiperResult<Vec<i16>> {
+        self.synthesize_to_samples_with_cancellation(text, &CancellationToken::new())
+    }
+    pub fn synthesize_to_samples_with_cancellation(
+        &self,
+        text: String,
+        cancel_token: &CancellationToken,
+    ) -> PiperResult<Vec<i16>> {
         if text.is_empty() {
             return Ok(vec![]);
         }
 
         let mut samples: Vec<i16> = Vec::new();
-        for result in self.synthesize_parallel(text)? {
+        for result in self.synthesize_parallel_with_cancellation(text, cancel_token)? {
             match result {
                 Ok(ws) => {
                     samples.append(&mut ws.to_vec());
@@ -90,49 +266,182 @@ impl PiperSpeechSynthesizer {
     pub fn synthesize_to_wav_buffer(&self, text: String) -> PiperResult<Vec<u8>> {
         let samples = self.synthesize_to_samples(text)?;
 
+        let (sample_rate, num_channels, sample_width) = self.wave_format()?;
         let mut wave_buffer = Vec::new();
         wave_writer::write_wave_samples_to_buffer(
             Cursor::new(&mut wave_buffer),
             samples.iter(),
-            self.0.wave_info()?.sample_rate as u32,
-            self.0.wave_info()?.num_channels.try_into().unwrap(),
-            self.0.wave_info()?.sample_width.try_into().unwrap(),
+            sample_rate,
+            num_channels,
+            sample_width,
         )?;
         Ok(wave_buffer)
     }
+    /// Synthesizes text segments in different languages one sentence at a time,
+    /// resampled to the sample rate of the default model
+    pub fn synthesize_segments(
+        &self,
+        segments: Vec<TextSegment>,
+    ) -> PiperResult<PiperSpeechStreamSegments> {
+        self.synthesize_segments_with_cancellation(segments, &CancellationToken::new())
+    }
+    pub fn synthesize_segments_with_cancellation(
+        &self,
+        segments: Vec<TextSegment>,
+        cancel_token: &CancellationToken,
+    ) -> PiperResult<PiperSpeechStreamSegments> {
+        let providers = Vec::from_iter(
+            segments
+                .into_iter()
+                .map(|segment| self.create_segment_task_provider(segment, cancel_token)),
+        );
+        PiperSpeechStreamSegments::new(providers, self.model.wave_info()?.sample_rate)
+    }
+    /// Replaces the detector used by [`PiperSpeechSynthesizer::synthesize_auto`], e.g. to restrict it to the languages there are voices for
+    #[cfg(feature = "langid")]
+    pub fn with_language_detector(mut self, language_detector: LanguageDetector) -> Self {
+        self.language_detector = language_detector;
+        self
+    }
+    /// Splits text into paragraphs tagged with their detected language, consecutive paragraphs in the same language are merged
+    ///
+    /// Paragraphs whose language can not be detected keep the language of the previous one.
+    #[cfg(feature = "langid")]
+    pub fn detect_segments(&self, text: &str) -> Vec<TextSegment> {
+        let mut segments: Vec<TextSegment> = Vec::new();
+        for paragraph in langid::split_paragraphs(text) {
+            let detected = self
+                .language_detector
+                .detect(paragraph)
+                .map(|detected| detected.language.to_string());
+            match segments.last_mut() {
+                Some(last) if detected.is_none() || last.language == detected => {
+                    last.text.push('\n');
+                    last.text.push_str(paragraph);
+                }
+                _ => segments.push(TextSegment {
+                    text: paragraph.to_string(),
+                    language: detected,
+                }),
+            }
+        }
+        segments
+    }
+    /// Synthesizes text in any number of languages, picking the voice for each paragraph by its detected language
+    #[cfg(feature = "langid")]
+    pub fn synthesize_auto(&self, text: String) -> PiperResult<PiperSpeechStreamSegments> {
+        self.synthesize_auto_with_cancellation(text, &CancellationToken::new())
+    }
+    #[cfg(feature = "langid")]
+    pub fn synthesize_auto_with_cancellation(
+        &self,
+        text: String,
+        cancel_token: &CancellationToken,
+    ) -> PiperResult<PiperSpeechStreamSegments> {
+        self.synthesize_segments_with_cancellation(self.detect_segments(&text), cancel_token)
+    }
+    /// Synthesizes an SSML document, switching languages as marked by `xml:lang`
+    pub fn synthesize_ssml(&self, ssml: &str) -> PiperResult<PiperSpeechStreamSegments> {
+        self.synthesize_segments(ssml::parse_ssml(ssml)?)
+    }
     pub fn synthesize_to_wav_file(&self, filename: &str, text: String) -> PiperResult<()> {
         let samples = self.synthesize_to_samples(text)?;
+        let (sample_rate, num_channels, sample_width) = self.wave_format()?;
         Ok(wave_writer::write_wave_samples_to_file(
             filename.into(),
             samples.iter(),
-            self.0.wave_info()?.sample_rate as u32,
-            self.0.wave_info()?.num_channels.try_into().unwrap(),
-            self.0.wave_info()?.sample_width.try_into().unwrap(),
+            sample_rate,
+            num_channels,
+            sample_width,
         )?)
     }
+    /// Returns the model's (sample rate, number of channels, sample width) as expected by the wave writer
+    fn wave_format(&self) -> PiperResult<(u32, u32, u32)> {
+        let wave_info = self.model.wave_info()?;
+        let to_u32 = |value: usize| {
+            u32::try_from(value).map_err(|_| {
+                PiperError::UnsupportedFormat(format!(
+                    "Invalid wave info: {} Hz, {} channel(s), {} byte samples",
+                    wave_info.sample_rate, wave_info.num_channels, wave_info.sample_width
+                ))
+            })
+        };
+        Ok((
+            to_u32(wave_info.sample_rate)?,
+            to_u32(wave_info.num_channels)?,
+            to_u32(wave_info.sample_width)?,
+        ))
+    }
 }
 
 struct SpeechSynthesisTaskProvider {
     model: Arc<dyn PiperModel + Sync + Send>,
+    /// Text as passed to the synthesizer, which the sources of the synthesized sentences refer to
     text: String,
+    normalized: NormalizedText,
+    /// Language to phonemize the text in instead of the model's own
+    language: Option<String>,
+    cancel_token: CancellationToken,
 }
 
 impl SpeechSynthesisTaskProvider {
-    fn get_phonemes(&self) -> PiperResult<Vec<String>> {
-        Ok(self.model.phonemize_text(&self.text)?.to_vec())
+    /// Phonemizes the normalized text, with sentence sources mapped back to the text before normalization
+    fn get_phonemes(&self) -> PiperResult<Phonemes> {
+        self.check_cancelled()?;
+        let phonemes = match self.language {
+            Some(ref language) => self
+                .model
+                .phonemize_text_with_language(&self.normalized.text, language)?,
+            None => self.model.phonemize_text(&self.normalized.text)?,
+        };
+        Ok(phonemes.map_sources(|source| {
+            let range = self.normalized.source_range(source.bytes);
+            Some(TextSpan::new(&self.text, range))
+        }))
     }
-    fn process_one_sentence(&self, phonemes: String) -> PiperWaveResult {
-        self.model.speak_one_sentence(phonemes)
+    fn process_one_sentence(&self, phonemes: String, source: Option<TextSpan>) -> PiperWaveResult {
+        self.check_cancelled()?;
+        match (self.model.speak_one_sentence(phonemes), source) {
+            (Ok(wave), source) => Ok(wave.with_source(source)),
+            (Err(e), Some(span)) => Err(PiperError::SentenceFailed {
+                span,
+                source: Box::new(e),
+            }),
+            (Err(e), None) => Err(e),
+        }
     }
     #[allow(dead_code)]
     fn process_batches(&self, phonemes: Vec<String>) -> PiperResult<Vec<PiperWaveSamples>> {
+        self.check_cancelled()?;
         self.model.speak_batch(phonemes)
     }
+    fn check_cancelled(&self) -> PiperResult<()> {
+        if self.cancel_token.is_cancelled() {
+            Err(PiperError::Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Passes results through until the first cancellation error, which ends the stream
+fn stop_after_cancellation(
+    finished: &mut bool,
+    result: Option<PiperWaveResult>,
+) -> Option<PiperWaveResult> {
+    if *finished {
+        return None;
+    }
+    if let Some(Err(PiperError::Cancelled)) = result {
+        *finished = true;
+    }
+    result
 }
 
 pub struct PiperSpeechStreamLazy {
     provider: SpeechSynthesisTaskProvider,
-    sentence_phonemes: std::vec::IntoIter<String>,
+    sentence_phonemes: <Phonemes as IntoIterator>::IntoIter,
+    finished: bool,
 }
 
 impl PiperSpeechStreamLazy {
@@ -141,6 +450,7 @@ impl PiperSpeechStreamLazy {
         Ok(Self {
             provider,
             sentence_phonemes,
+            finished: false,
         })
     }
 }
@@ -149,28 +459,31 @@ impl Iterator for PiperSpeechStreamLazy {
     type Item = PiperWaveResult;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let next_batch = self.sentence_phonemes.next()?;
-        match self.provider.process_one_sentence(next_batch) {
-            Ok(ws) => Some(Ok(ws)),
-            Err(e) => Some(Err(e)),
+        if self.finished {
+            return None;
         }
+        let (phonemes, source) = self.sentence_phonemes.next()?;
+        let result = self.provider.process_one_sentence(phonemes, source);
+        stop_after_cancellation(&mut self.finished, Some(result))
     }
 }
 
 #[must_use]
 pub struct PiperSpeechStreamParallel {
     precalculated_results: std::vec::IntoIter<PiperWaveResult>,
+    finished: bool,
 }
 
 impl PiperSpeechStreamParallel {
     fn new(provider: SpeechSynthesisTaskProvider) -> PiperResult<Self> {
-        let calculated_result: Vec<PiperWaveResult> = provider
-            .get_phonemes()?
-            .par_iter()
-            .map(|ph| provider.process_one_sentence(ph.to_string()))
+        let sentences = Vec::from_iter(provider.get_phonemes()?);
+        let calculated_result: Vec<PiperWaveResult> = sentences
+            .into_par_iter()
+            .map(|(phonemes, source)| provider.process_one_sentence(phonemes, source))
             .collect();
         Ok(Self {
             precalculated_results: calculated_result.into_iter(),
+            finished: false,
         })
     }
 }
@@ -179,16 +492,18 @@ impl Iterator for PiperSpeechStreamParallel {
     type Item = PiperWaveResult;
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.precalculated_results.next()
+        let result = self.precalculated_results.next();
+        stop_after_cancellation(&mut self.finished, result)
     }
 }
 
 #[must_use]
 pub struct PiperSpeechStreamBatched {
     provider: Arc<SpeechSynthesisTaskProvider>,
-    sentence_phonemes: std::vec::IntoIter<String>,
+    sentence_phonemes: <Phonemes as IntoIterator>::IntoIter,
     channel: SpeechSynthesisChannel,
     batch_size: usize,
+    finished: bool,
 }
 
 impl PiperSpeechStreamBatched {
@@ -199,11 +514,15 @@ impl PiperSpeechStreamBatched {
             sentence_phonemes,
             channel: SpeechSynthesisChannel::new(batch_size)?,
             batch_size,
+            finished: false,
         };
         instance.send_batch();
         Ok(instance)
     }
     fn send_batch(&mut self) {
+        if self.provider.cancel_token.is_cancelled() {
+            return;
+        }
         let next_batch = Vec::from_iter((&mut self.sentence_phonemes).take(self.batch_size));
         if !next_batch.is_empty() {
             let provider = Arc::clone(&self.provider);
@@ -217,30 +536,85 @@ impl Iterator for PiperSpeechStreamBatched {
 
     fn next(&mut self) -> Option<Self::Item> {
         self.send_batch();
-        self.channel.get()
+        let mut result = self.channel.get();
+        if result.is_none() && self.sentence_phonemes.len() > 0 {
+            // Remaining sentences were never queued because synthesis was cancelled
+            result = Some(Err(PiperError::Cancelled));
+        }
+        stop_after_cancellation(&mut self.finished, result)
     }
 }
 
-struct SpeechSynthesisTask(Arc<OnceCell<PiperWaveResult>>);
+impl Drop for PiperSpeechStreamBatched {
+    fn drop(&mut self) {
+        // Prevents tasks that are still queued on the thread pool from running
+        self.provider.cancel_token.cancel();
+    }
+}
+
+/// Speaks segments one after another, phonemizing each segment when its first sentence is requested
+#[must_use]
+pub struct PiperSpeechStreamSegments {
+    providers: std::vec::IntoIter<SpeechSynthesisTaskProvider>,
+    current: Option<PiperSpeechStreamLazy>,
+    sample_rate: usize,
+    finished: bool,
+}
+
+impl PiperSpeechStreamSegments {
+    fn new(providers: Vec<SpeechSynthesisTaskProvider>, sample_rate: usize) -> PiperResult<Self> {
+        Ok(Self {
+            providers: providers.into_iter(),
+            current: None,
+            sample_rate,
+            finished: false,
+        })
+    }
+}
+
+impl Iterator for PiperSpeechStreamSegments {
+    type Item = PiperWaveResult;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+        let result = loop {
+            if let Some(result) = self.current.as_mut().and_then(|stream| stream.next()) {
+                break Some(result);
+            }
+            match PiperSpeechStreamLazy::new(self.providers.next()?) {
+                Ok(stream) => self.current = Some(stream),
+                Err(e) => break Some(Err(e)),
+            }
+        };
+        let result = result.map(|wave| wave.map(|samples| samples.resample(self.sample_rate)));
+        stop_after_cancellation(&mut self.finished, result)
+    }
+}
+
+struct SpeechSynthesisTask(mpsc::Receiver<PiperWaveResult>);
 
 impl SpeechSynthesisTask {
-    fn new(provider: Arc<SpeechSynthesisTaskProvider>, phonemes: String) -> Self {
-        let instance = Self(Arc::new(OnceCell::new()));
-        let result = Arc::clone(&instance.0);
+    fn new(
+        provider: Arc<SpeechSynthesisTaskProvider>,
+        phonemes: String,
+        source: Option<TextSpan>,
+    ) -> Self {
+        let (sender, receiver) = mpsc::sync_channel(1);
         SYNTHESIS_THREAD_POOL.spawn_fifo(move || {
-            let wave_result = provider.process_one_sentence(phonemes);
-            result.set(wave_result).unwrap();
+            let wave_result = provider.process_one_sentence(phonemes, source);
+            // The receiving stream may have been dropped already
+            sender.send(wave_result).ok();
         });
-        instance
+        Self(receiver)
     }
     fn get_result(self) -> PiperWaveResult {
-        self.0.wait();
-        if let Ok(result) = Arc::try_unwrap(self.0) {
-            result.into_inner().unwrap()
-        } else {
-            Err(PiperError::OperationError(
+        match self.0.recv() {
+            Ok(result) => result,
+            Err(_) => Err(PiperError::OperationError(
                 "Failed to obtain results".to_string(),
-            ))
+            )),
         }
     }
 }
@@ -255,13 +629,163 @@ impl SpeechSynthesisChannel {
             task_queue: VecDeque::with_capacity(batch_size * 4),
         })
     }
-    fn put(&mut self, provider: Arc<SpeechSynthesisTaskProvider>, batch: Vec<String>) {
-        for phonemes in batch.into_iter() {
-            self.task_queue
-                .push_back(SpeechSynthesisTask::new(Arc::clone(&provider), phonemes));
+    fn put(
+        &mut self,
+        provider: Arc<SpeechSynthesisTaskProvider>,
+        batch: Vec<(String, Option<TextSpan>)>,
+    ) {
+        for (phonemes, source) in batch.into_iter() {
+            self.task_queue.push_back(SpeechSynthesisTask::new(
+                Arc::clone(&provider),
+                phonemes,
+                source,
+            ));
         }
     }
     fn get(&mut self) -> Option<PiperWaveResult> {
         self.task_queue.pop_front().map(|task| task.get_result())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::PiperErrorKind;
+    use crate::test_util::TestModel;
+
+    fn synthesizer() -> PiperSpeechSynthesizer {
+        PiperSpeechSynthesizer::new(Arc::new(TestModel::new(16000).with_language("en-us"))).unwrap()
+    }
+
+    #[test]
+    fn test_normalizes_before_phonemization() {
+        let text = "It is 10:00.".to_string();
+        assert_eq!(
+            synthesizer().normalize_text(text.clone()),
+            "It is ten o'clock."
+        );
+        assert_eq!(
+            synthesizer()
+                .without_normalizer()
+                .normalize_text(text.clone()),
+            text
+        );
+    }
+
+    #[test]
+    fn test_waves_keep_their_source_text() -> PiperResult<()> {
+        let text = "It is 10:00. Bye".to_string();
+        let sources = |waves: Vec<PiperWaveSamples>| {
+            Vec::from_iter(waves.into_iter().map(|wave| wave.source.unwrap().text))
+        };
+        let lazy = synthesizer().synthesize_lazy(text.clone())?;
+        assert_eq!(
+            sources(lazy.collect::<PiperResult<_>>()?),
+            ["It is 10:00.", " Bye"]
+        );
+        let batched = synthesizer().synthesize_batched(text.clone(), Some(1))?;
+        assert_eq!(
+            sources(batched.collect::<PiperResult<_>>()?),
+            ["It is 10:00.", " Bye"]
+        );
+
+        let results = Vec::from_iter(synthesizer().synthesize_parallel("ok.error".to_string())?);
+        assert_eq!(
+            results[0].as_ref().unwrap().source.as_ref().unwrap().chars,
+            0..2
+        );
+        match &results[1] {
+            Err(e @ PiperError::SentenceFailed { span, .. }) => {
+                assert_eq!(span.bytes, 3..8);
+                assert_eq!(e.kind(), PiperErrorKind::OperationFailed);
+                // The cause is reported by `source`, not repeated in the message
+                assert_eq!(e.to_string(), "Failed to synthesize `error` (bytes 3..8)");
+                assert!(std::error::Error::source(e).is_some());
+            }
+            _ => panic!("expected the failing sentence to be reported"),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_segments_are_routed_and_resampled() -> PiperResult<()> {
+        let default_model = Arc::new(TestModel::new(16000));
+        let russian_model = Arc::new(TestModel::new(8000));
+        let synthesizer = PiperSpeechSynthesizer::new(default_model.clone())?
+            .with_voice("ru", russian_model.clone());
+        let segments = vec![
+            TextSegment::new("Hello", None),
+            TextSegment::new("Привет", Some("ru-RU")),
+            TextSegment::new("Hallo", Some("de")),
+        ];
+        let waves = synthesizer
+            .synthesize_segments(segments)?
+            .collect::<PiperResult<Vec<_>>>()?;
+        assert_eq!(waves.len(), 3);
+        for wave in waves {
+            assert_eq!(wave.info.sample_rate, 16000);
+            assert_eq!(wave.len(), 16000);
+        }
+        assert_eq!(default_model.languages(), ["own", "de"]);
+        assert_eq!(russian_model.languages(), ["own"]);
+        Ok(())
+    }
+
+    #[cfg(feature = "langid")]
+    #[test]
+    fn test_auto_voice_selection() -> PiperResult<()> {
+        let default_model = Arc::new(TestModel::new(16000));
+        let russian_model = Arc::new(TestModel::new(16000));
+        let synthesizer = PiperSpeechSynthesizer::new(default_model.clone())?
+            .with_voice("ru", russian_model.clone());
+        let text = "The weather is nice today and we are going to the beach.\n\n\
+            Сегодня хорошая погода, и мы идём на пляж.\n\n\
+            Il fait beau aujourd'hui et nous allons à la plage.";
+        let waves = synthesizer
+            .synthesize_auto(text.to_string())?
+            .collect::<PiperResult<Vec<_>>>()?;
+        assert_eq!(waves.len(), 3);
+        assert_eq!(default_model.languages(), ["en", "fr"]);
+        assert_eq!(russian_model.languages(), ["own"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_cancelled_before_start() {
+        let token = CancellationToken::new();
+        token.cancel();
+        let result = synthesizer().synthesize_lazy_with_cancellation("a.b.c".to_string(), &token);
+        assert!(matches!(result, Err(PiperError::Cancelled)));
+    }
+
+    #[test]
+    fn test_batched_stream_ends_after_cancellation() {
+        let token = CancellationToken::new();
+        let text = "a.b.c.d.e.f.g.h.i.j.k.l".to_string();
+        let mut stream = synthesizer()
+            .synthesize_batched_with_cancellation(text, Some(2), &token)
+            .unwrap();
+        assert!(stream.next().unwrap().is_ok());
+        token.cancel();
+        let rest = Vec::from_iter(stream);
+        assert!(matches!(rest.last(), Some(Err(PiperError::Cancelled))));
+        assert_eq!(
+            rest.iter()
+                .filter(|r| matches!(r, Err(PiperError::Cancelled)))
+                .count(),
+            1
+        );
+        assert!(rest.len() < 11);
+    }
+
+    #[test]
+    fn test_child_token_does_not_cancel_parent() {
+        let parent = CancellationToken::new();
+        let child = parent.child_token();
+        child.cancel();
+        assert!(!parent.is_cancelled());
+        let child = parent.child_token();
+        parent.cancel();
+        assert!(child.is_cancelled());
+    }
+}
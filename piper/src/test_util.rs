@@ -0,0 +1,106 @@
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use crate::core::{
+    Phonemes, PiperError, PiperModel, PiperResult, PiperWaveInfo, PiperWaveResult,
+    PiperWaveSamples, TextSpan,
+};
+
+/// Path in the temporary directory that is unique to this test process
+pub(crate) fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("piper-{}-test-{}", name, std::process::id()))
+}
+
+/// Model that speaks one second of silence per sentence without running inference
+///
+/// Sentences end at periods and blank ones are skipped, phonemes containing `error` fail to synthesize,
+/// and the noise a real model adds is stood in for by raising the first sample.
+#[derive(Clone)]
+pub(crate) struct TestModel {
+    sample_rate: usize,
+    language: Option<String>,
+    without_noise: bool,
+    sentences: Arc<AtomicUsize>,
+    languages: Arc<Mutex<Vec<String>>>,
+}
+
+impl TestModel {
+    pub(crate) fn new(sample_rate: usize) -> Self {
+        Self {
+            sample_rate,
+            language: None,
+            without_noise: false,
+            sentences: Default::default(),
+            languages: Default::default(),
+        }
+    }
+    pub(crate) fn with_language(mut self, language: &str) -> Self {
+        self.language = Some(language.to_string());
+        self
+    }
+    /// Number of sentences synthesized so far, including those of models made by [`PiperModel::without_noise`]
+    pub(crate) fn sentences(&self) -> usize {
+        self.sentences.load(Ordering::Relaxed)
+    }
+    /// Languages of the phonemized texts, `own` for the model's own language
+    pub(crate) fn languages(&self) -> Vec<String> {
+        self.languages.lock().unwrap().clone()
+    }
+}
+
+impl PiperModel for TestModel {
+    fn phonemize_text(&self, text: &str) -> PiperResult<Phonemes> {
+        self.phonemize_text_with_language(text, "own")
+    }
+    fn phonemize_text_with_language(&self, text: &str, language: &str) -> PiperResult<Phonemes> {
+        self.languages.lock().unwrap().push(language.to_string());
+        let mut phonemes = Phonemes::new();
+        let mut start = 0;
+        for sentence in text.split('.') {
+            if !sentence.trim().is_empty() {
+                let span = TextSpan::new(text, start..start + sentence.len());
+                phonemes.push(sentence.to_string(), Some(span));
+            }
+            start += sentence.len() + 1;
+        }
+        Ok(phonemes)
+    }
+    fn speak_batch(&self, phoneme_batches: Vec<String>) -> PiperResult<Vec<PiperWaveSamples>> {
+        phoneme_batches
+            .into_iter()
+            .map(|phonemes| self.speak_one_sentence(phonemes))
+            .collect()
+    }
+    fn speak_one_sentence(&self, phonemes: String) -> PiperWaveResult {
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        if phonemes.contains("error") {
+            return Err(PiperError::OperationError("Test error".to_string()));
+        }
+        self.sentences.fetch_add(1, Ordering::Relaxed);
+        let mut samples = vec![0; self.sample_rate];
+        if !self.without_noise {
+            samples[0] = 1;
+        }
+        Ok(PiperWaveSamples::new(samples, self.sample_rate, Some(1.0)))
+    }
+    fn wave_info(&self) -> PiperResult<PiperWaveInfo> {
+        Ok(PiperWaveInfo {
+            sample_rate: self.sample_rate,
+            num_channels: 1,
+            sample_width: 2,
+        })
+    }
+    fn language(&self) -> Option<String> {
+        self.language.clone()
+    }
+    fn synthesis_fingerprint(&self) -> Option<String> {
+        Some(format!("test-{}", self.sample_rate))
+    }
+    fn without_noise(&self) -> Option<Arc<dyn PiperModel + Sync + Send>> {
+        Some(Arc::new(Self {
+            without_noise: true,
+            ..self.clone()
+        }))
+    }
+}
@@ -2,7 +2,7 @@ use std::{
     collections::HashMap,
     fs::File,
     path::PathBuf,
-    sync::{Arc, RwLock},
+    sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard},
 };
 
 use serde::Deserialize;
@@ -12,22 +12,38 @@ use once_cell::sync::{Lazy, OnceCell};
 use ndarray::{Array1, Array2, CowArray};
 use ndarray_stats::QuantileExt;
 
-use ort::{tensor::OrtOwnedTensor, Environment, GraphOptimizationLevel, SessionBuilder, Value};
+use ort::{
+    tensor::{OrtOwnedTensor, TensorElementDataType},
+    Environment, GraphOptimizationLevel, SessionBuilder, Value,
+};
 
 use crate::core::{
     Phonemes, PiperError, PiperModel, PiperResult, PiperWaveInfo, PiperWaveResult, PiperWaveSamples,
 };
-use crate::phonemize::text_to_phonemes;
+use crate::phonemizer::{
+    find_espeak_voice, list_espeak_voices, CachingPhonemizer, ESpeakPhonemizer,
+    PassthroughPhonemizer, PhonemeOptions, Phonemizer, DEFAULT_PRESERVED_PUNCTUATION,
+};
 
 //----------------------------------------------------------------
 
 const MAX_WAV_VALUE: f32 = 32767.0;
-const BOS: char = '^';
-const EOS: char = '$';
-const PAD: char = '_';
+const BOS: &str = "^";
+const EOS: &str = "$";
+const PAD: &str = "_";
+
+/// Names of the inputs of piper's VITS graph, in the order they are fed to the session
+const INPUT_PHONEMES: &str = "input";
+const INPUT_LENGTHS: &str = "input_lengths";
+const INPUT_SCALES: &str = "scales";
+const INPUT_SPEAKER_ID: &str = "sid";
 
 static CPU_COUNT: Lazy<i16> = Lazy::new(|| num_cpus::get().try_into().unwrap_or(4));
 
+/// onnxruntime environment shared by all models that are not given one explicitly
+static DEFAULT_ORT_ENVIRONMENT: Lazy<Arc<Environment>> =
+    Lazy::new(|| Arc::new(Environment::default()));
+
 #[derive(Deserialize, Default)]
 pub struct AudioConfig {
     pub sample_rate: u32,
@@ -52,12 +68,177 @@ pub struct ModelConfig {
     pub speaker_id_map: HashMap<String, i64>,
     espeak: ESpeakConfig,
     inference: InferenceConfig,
-    #[allow(dead_code)]
     num_symbols: u32,
     phoneme_type: Option<String>,
-    #[allow(dead_code)]
-    phoneme_map: HashMap<char, Vec<char>>,
-    phoneme_id_map: HashMap<char, Vec<i64>>,
+    /// Replaces phonemes produced by the phonemizer with sequences of the voice's own phonemes
+    phoneme_map: HashMap<String, Vec<String>>,
+    /// Keys may span several code points, e.g. a base letter with combining diacritics or tied affricates
+    phoneme_id_map: HashMap<String, Vec<i64>>,
+}
+
+impl ModelConfig {
+    fn symbol_id(&self, symbol: &str) -> PiperResult<i64> {
+        match self.phoneme_id_map.get(symbol).and_then(|ids| ids.first()) {
+            Some(id) => Ok(*id),
+            None => Err(PiperError::InvalidConfig {
+                message: format!(
+                    "Required symbol `{}` is missing from `phoneme_id_map`",
+                    symbol
+                ),
+                source: None,
+            }),
+        }
+    }
+    /// The symbols of [`DEFAULT_PRESERVED_PUNCTUATION`] the voice knows
+    fn known_punctuation(&self) -> String {
+        String::from_iter(
+            DEFAULT_PRESERVED_PUNCTUATION
+                .chars()
+                .filter(|symbol| self.is_known_phoneme(&symbol.to_string())),
+        )
+    }
+    fn is_known_phoneme(&self, phoneme: &str) -> bool {
+        self.phoneme_map.contains_key(phoneme) || self.phoneme_id_map.contains_key(phoneme)
+    }
+    /// Ids of a known phoneme after applying `phoneme_map`
+    fn ids_of_phoneme(&self, phoneme: &str) -> Vec<i64> {
+        let ids_of = |phoneme: &str| self.phoneme_id_map.get(phoneme).into_iter().flatten();
+        match self.phoneme_map.get(phoneme) {
+            Some(mapped) => mapped.iter().flat_map(|p| ids_of(p)).copied().collect(),
+            None => ids_of(phoneme).copied().collect(),
+        }
+    }
+    /// Maps phonemes to the ids fed to the model, wrapped in BOS/EOS with PAD after every id
+    ///
+    /// Phonemes are matched longest first against the keys of `phoneme_map` and `phoneme_id_map`,
+    /// without splitting a character from its combining marks unless the voice only knows them separately.
+    /// Phonemes missing from both maps are dropped, or reported as an error if `strict` is set.
+    fn phonemes_to_input_ids(&self, phonemes: &str, strict: bool) -> PiperResult<Vec<i64>> {
+        let pad_id = self.symbol_id(PAD)?;
+        let bos_id = self.symbol_id(BOS)?;
+        let eos_id = self.symbol_id(EOS)?;
+        let max_phoneme_len = self
+            .phoneme_map
+            .keys()
+            .chain(self.phoneme_id_map.keys())
+            .map(String::len)
+            .max()
+            .unwrap_or(0);
+        let cluster_ends = cluster_ends(phonemes);
+        let mut phoneme_ids: Vec<i64> = Vec::with_capacity((phonemes.len() + 1) * 2);
+        let mut unknown_phonemes: Vec<String> = Vec::new();
+        phoneme_ids.push(bos_id);
+        let mut start = 0;
+        while start < phonemes.len() {
+            let next_clusters = &cluster_ends[cluster_ends.partition_point(|end| *end <= start)..];
+            let candidates = &next_clusters
+                [..next_clusters.partition_point(|end| *end - start <= max_phoneme_len)];
+            let longest_match = candidates
+                .iter()
+                .rev()
+                .find(|end| self.is_known_phoneme(&phonemes[start..**end]));
+            // The cluster as a whole is unknown, the voice may still know its base character and marks separately
+            let char_end = start + phonemes[start..].chars().next().map_or(1, char::len_utf8);
+            let end = match longest_match {
+                Some(end) => *end,
+                None if self.is_known_phoneme(&phonemes[start..char_end]) => char_end,
+                None => {
+                    let phoneme = phonemes[start..next_clusters[0]].to_string();
+                    if !unknown_phonemes.contains(&phoneme) {
+                        unknown_phonemes.push(phoneme);
+                    }
+                    start = next_clusters[0];
+                    continue;
+                }
+            };
+            for id in self.ids_of_phoneme(&phonemes[start..end]) {
+                phoneme_ids.push(id);
+                phoneme_ids.push(pad_id);
+            }
+            start = end;
+        }
+        if strict && !unknown_phonemes.is_empty() {
+            return Err(PiperError::UnknownPhonemes(unknown_phonemes));
+        }
+        phoneme_ids.push(eos_id);
+        Ok(phoneme_ids)
+    }
+    /// Checks that externally supplied phoneme ids can be fed to the model
+    fn check_phoneme_ids(&self, phoneme_ids: &[i64]) -> PiperResult<()> {
+        if phoneme_ids.is_empty() {
+            return Err(PiperError::OperationError(
+                "Empty phoneme input".to_string(),
+            ));
+        }
+        if let Some(id) = phoneme_ids.iter().find(|id| **id < 0) {
+            return Err(PiperError::OperationError(format!(
+                "Phoneme id {} is negative",
+                id
+            )));
+        }
+        // Like `validate`, a `num_symbols` of 0 means the number of symbols is unknown
+        match phoneme_ids
+            .iter()
+            .find(|id| self.num_symbols > 0 && **id >= self.num_symbols as i64)
+        {
+            Some(id) => Err(PiperError::OperationError(format!(
+                "Phoneme id {} is outside the range of the model's {} symbols",
+                id, self.num_symbols
+            ))),
+            None => Ok(()),
+        }
+    }
+    /// Checks the config for inconsistencies that would otherwise surface as panics or opaque errors during inference
+    fn validate(&self) -> Result<(), String> {
+        for symbol in [PAD, BOS, EOS] {
+            match self.phoneme_id_map.get(symbol) {
+                Some(ids) if !ids.is_empty() => (),
+                _ => {
+                    return Err(format!(
+                        "Required symbol `{}` is missing from `phoneme_id_map`",
+                        symbol
+                    ))
+                }
+            }
+        }
+        for (phoneme, mapped) in self.phoneme_map.iter() {
+            if let Some(target) = mapped
+                .iter()
+                .find(|target| !self.phoneme_id_map.contains_key(*target))
+            {
+                return Err(format!(
+                    "`phoneme_map` maps `{}` to `{}` which is missing from `phoneme_id_map`",
+                    phoneme, target
+                ));
+            }
+        }
+        let all_ids = || self.phoneme_id_map.values().flatten();
+        if let Some(min_id) = all_ids().min() {
+            if *min_id < 0 {
+                return Err(format!(
+                    "`phoneme_id_map` contains negative id `{}`",
+                    min_id
+                ));
+            }
+        }
+        if let Some(max_id) = all_ids().max() {
+            if self.num_symbols > 0 && *max_id >= self.num_symbols as i64 {
+                return Err(format!(
+                    "`phoneme_id_map` contains id `{}` which is out of range for `num_symbols` = {}",
+                    max_id, self.num_symbols
+                ));
+            }
+        }
+        for (name, sid) in self.speaker_id_map.iter() {
+            if *sid < 0 || *sid >= self.num_speakers.max(1) as i64 {
+                return Err(format!(
+                    "Speaker `{}` has id `{}` which is out of range for `num_speakers` = {}",
+                    name, sid, self.num_speakers
+                ));
+            }
+        }
+        Ok(())
+    }
 }
 
 #[derive(Debug, Clone, Default)]
@@ -68,46 +249,199 @@ pub struct SynthesisConfig {
     noise_w: f32,
 }
 
+impl SynthesisConfig {
+    /// The `scales` input of the model, see [`PiperModel::without_noise`] for `zero_noise`
+    fn scales(&self, zero_noise: bool) -> [f32; 3] {
+        match zero_noise {
+            true => [0.0, self.length_scale, 0.0],
+            false => [self.noise_scale, self.length_scale, self.noise_w],
+        }
+    }
+}
+
+/// Options used when creating the onnxruntime inference session of a model
+#[derive(Debug)]
+pub struct SessionOptions {
+    pub optimization_level: GraphOptimizationLevel,
+    pub inter_threads: i16,
+    pub intra_threads: i16,
+    pub parallel_execution: bool,
+    pub memory_pattern: bool,
+}
+
+impl Default for SessionOptions {
+    fn default() -> Self {
+        Self {
+            optimization_level: GraphOptimizationLevel::Level3,
+            inter_threads: *CPU_COUNT / 2,
+            intra_threads: *CPU_COUNT / 2,
+            parallel_execution: true,
+            memory_pattern: true,
+        }
+    }
+}
+
+/// Builder for [`VitsModel`]
+///
+/// Uses a process wide default onnxruntime environment unless one is given with [`VitsModelBuilder::environment`].
+pub struct VitsModelBuilder {
+    config_path: PathBuf,
+    onnx_path: PathBuf,
+    ort_env: Option<Arc<Environment>>,
+    session_options: SessionOptions,
+    phonemizer: Option<Arc<dyn Phonemizer>>,
+    strict_phonemes: bool,
+    preserve_punctuation: bool,
+    phoneme_cache_capacity: Option<usize>,
+}
+
+impl VitsModelBuilder {
+    pub fn environment(mut self, ort_env: Arc<Environment>) -> Self {
+        self.ort_env = Some(ort_env);
+        self
+    }
+    pub fn session_options(mut self, session_options: SessionOptions) -> Self {
+        self.session_options = session_options;
+        self
+    }
+    /// Overrides the phonemizer, which defaults to eSpeak-ng or passthrough depending on the config's `phoneme_type`
+    pub fn phonemizer(mut self, phonemizer: Arc<dyn Phonemizer>) -> Self {
+        self.phonemizer = Some(phonemizer);
+        self
+    }
+    /// Fails synthesis with [`PiperError::UnknownPhonemes`] instead of dropping phonemes the voice does not know
+    pub fn strict_phonemes(mut self, strict: bool) -> Self {
+        self.strict_phonemes = strict;
+        self
+    }
+    /// Keeps the punctuation of the text that the voice has symbols for, such as `;` or `…`,
+    /// instead of only `.`, `,`, `?` and `!`. Applies to the default eSpeak-ng phonemizer only.
+    pub fn preserve_punctuation(mut self, preserve: bool) -> Self {
+        self.preserve_punctuation = preserve;
+        self
+    }
+    /// Caches the phonemes of up to `capacity` texts in front of the phonemizer, see [`VitsModel::phoneme_cache`]
+    pub fn phoneme_cache(mut self, capacity: usize) -> Self {
+        self.phoneme_cache_capacity = Some(capacity);
+        self
+    }
+    pub fn build(self) -> PiperResult<VitsModel> {
+        let (config, synth_config) = VitsModel::load_model_config(&self.config_path)?;
+        let speaker_map = reversed_mapping(&config.speaker_id_map);
+        let phonemizer = match self.phonemizer {
+            Some(phonemizer) => phonemizer,
+            None if config.phoneme_type.as_deref() == Some("text") => {
+                Arc::new(PassthroughPhonemizer)
+            }
+            None => {
+                check_espeak_voice(&config.espeak.voice)?;
+                let mut options = PhonemeOptions::default();
+                if self.preserve_punctuation {
+                    options = options.with_punctuation(config.known_punctuation());
+                }
+                Arc::new(ESpeakPhonemizer::new(options))
+            }
+        };
+        let phoneme_cache = self
+            .phoneme_cache_capacity
+            .map(|capacity| Arc::new(CachingPhonemizer::new(phonemizer.clone(), capacity)));
+        let phonemizer = match phoneme_cache {
+            Some(ref cache) => cache.clone() as Arc<dyn Phonemizer>,
+            None => phonemizer,
+        };
+        let model = VitsModel {
+            synth_config: Arc::new(RwLock::new(synth_config)),
+            config: Arc::new(config),
+            speaker_map,
+            onnx_path: self.onnx_path,
+            ort_env: self
+                .ort_env
+                .unwrap_or_else(|| Arc::clone(&DEFAULT_ORT_ENVIRONMENT)),
+            session_options: Arc::new(self.session_options),
+            session: Arc::new(OnceCell::new()),
+            phonemizer,
+            phoneme_cache,
+            strict_phonemes: self.strict_phonemes,
+            zero_noise: false,
+        };
+        model.validate_graph()?;
+        Ok(model)
+    }
+}
+
+/// The synthesis config, model config and inference session are shared with the model returned by
+/// [`PiperModel::without_noise`]
 pub struct VitsModel {
-    synth_config: RwLock<SynthesisConfig>,
-    config: ModelConfig,
+    synth_config: Arc<RwLock<SynthesisConfig>>,
+    config: Arc<ModelConfig>,
     speaker_map: HashMap<i64, String>,
     onnx_path: PathBuf,
-    ort_env: &'static Arc<Environment>,
-    session: OnceCell<Result<ort::Session, ort::OrtError>>,
+    ort_env: Arc<Environment>,
+    session_options: Arc<SessionOptions>,
+    session: Arc<OnceCell<ort::Session>>,
+    phonemizer: Arc<dyn Phonemizer>,
+    phoneme_cache: Option<Arc<CachingPhonemizer>>,
+    strict_phonemes: bool,
+    /// The graphs of piper voices draw their noise without a seed that can be set, so it is left out instead
+    zero_noise: bool,
 }
 
 impl VitsModel {
     pub fn new(
         config_path: PathBuf,
         onnx_path: PathBuf,
-        ort_env: &'static Arc<ort::Environment>,
+        ort_env: Arc<ort::Environment>,
     ) -> PiperResult<Self> {
-        match Self::load_model_config(&config_path) {
-            Ok((config, synth_config)) => {
-                let speaker_map = reversed_mapping(&config.speaker_id_map);
-                Ok(Self {
-                    synth_config: RwLock::new(synth_config),
-                    config,
-                    speaker_map,
-                    onnx_path,
-                    ort_env,
-                    session: OnceCell::new(),
-                })
-            }
-            Err(error) => Err(error),
+        Self::builder(config_path, onnx_path)
+            .environment(ort_env)
+            .build()
+    }
+    pub fn builder(config_path: PathBuf, onnx_path: PathBuf) -> VitsModelBuilder {
+        VitsModelBuilder {
+            config_path,
+            onnx_path,
+            ort_env: None,
+            session_options: SessionOptions::default(),
+            phonemizer: None,
+            strict_phonemes: false,
+            preserve_punctuation: false,
+            phoneme_cache_capacity: None,
+        }
+    }
+    /// The cache enabled with [`VitsModelBuilder::phoneme_cache`], for its statistics and to prewarm it
+    pub fn phoneme_cache(&self) -> Option<&CachingPhonemizer> {
+        self.phoneme_cache.as_deref()
+    }
+    /// Returns the ids the model is fed for `phonemes`, including the BOS, EOS and PAD symbols
+    pub fn phoneme_ids(&self, phonemes: &str) -> PiperResult<Vec<i64>> {
+        self.config
+            .phonemes_to_input_ids(phonemes, self.strict_phonemes)
+    }
+    /// Synthesizes one sentence from precomputed phoneme ids, as returned by [`VitsModel::phoneme_ids`]
+    pub fn speak_phoneme_ids(&self, phoneme_ids: Vec<i64>) -> PiperWaveResult {
+        self.config.check_phoneme_ids(&phoneme_ids)?;
+        self.infer_with_values(phoneme_ids)
+    }
+    /// Synthesizes several sentences of precomputed phoneme ids in one inference run
+    pub fn speak_phoneme_id_batches(
+        &self,
+        phoneme_id_batches: Vec<Vec<i64>>,
+    ) -> PiperResult<Vec<PiperWaveSamples>> {
+        for phoneme_ids in phoneme_id_batches.iter() {
+            self.config.check_phoneme_ids(phoneme_ids)?;
         }
+        self.infer_with_values_batched(phoneme_id_batches)
     }
     pub fn speakers(&self) -> PiperResult<HashMap<i64, String>> {
         Ok(self.speaker_map.clone())
     }
     pub fn get_speaker(&self) -> PiperResult<Option<String>> {
         if self.config.num_speakers == 0 {
-            return Err(PiperError::OperationError(
+            return Err(PiperError::InvalidSpeaker(
                 "This model is a single speaker model.".to_string(),
             ));
         }
-        if let Some(ref speaker) = self.synth_config.read().unwrap().speaker {
+        if let Some(ref speaker) = self.read_synth_config()?.speaker {
             Ok(Some(speaker.0.clone()))
         } else {
             let default_speaker = match self.speaker_map.get(&0) {
@@ -119,65 +453,61 @@ impl VitsModel {
     }
     pub fn set_speaker(&self, name: String) -> PiperResult<()> {
         if self.config.num_speakers == 0 {
-            return Err(PiperError::OperationError(
+            return Err(PiperError::InvalidSpeaker(
                 "This model is a single speaker model.".to_string(),
             ));
         }
         if let Some(sid) = self.config.speaker_id_map.get(&name) {
-            let mut synth_config = self.synth_config.write().unwrap();
+            let mut synth_config = self.write_synth_config()?;
             synth_config.speaker = Some((name, *sid));
             Ok(())
         } else {
-            Err(PiperError::OperationError(format!(
-                "Invalid speaker name: `{}`",
+            Err(PiperError::InvalidSpeaker(format!(
+                "Unknown speaker name: `{}`",
                 name
             )))
         }
     }
     pub fn get_noise_scale(&self) -> PiperResult<f32> {
-        Ok(self.synth_config.read().unwrap().noise_scale)
+        Ok(self.read_synth_config()?.noise_scale)
     }
     pub fn set_noise_scale(&self, value: f32) -> PiperResult<()> {
-        self.synth_config.write().unwrap().noise_scale = value;
+        self.write_synth_config()?.noise_scale = value;
         Ok(())
     }
     pub fn get_length_scale(&self) -> PiperResult<f32> {
-        Ok(self.synth_config.read().unwrap().length_scale)
+        Ok(self.read_synth_config()?.length_scale)
     }
     pub fn set_length_scale(&self, value: f32) -> PiperResult<()> {
-        self.synth_config.write().unwrap().length_scale = value;
+        self.write_synth_config()?.length_scale = value;
         Ok(())
     }
     pub fn get_noise_w(&self) -> PiperResult<f32> {
-        Ok(self.synth_config.read().unwrap().noise_w)
+        Ok(self.read_synth_config()?.noise_w)
     }
     pub fn set_noise_w(&self, value: f32) -> PiperResult<()> {
-        self.synth_config.write().unwrap().noise_w = value;
+        self.write_synth_config()?.noise_w = value;
         Ok(())
     }
+    fn read_synth_config(&self) -> PiperResult<RwLockReadGuard<'_, SynthesisConfig>> {
+        self.synth_config.read().map_err(|_| {
+            PiperError::OperationError("Synthesis config lock is poisoned".to_string())
+        })
+    }
+    fn write_synth_config(&self) -> PiperResult<RwLockWriteGuard<'_, SynthesisConfig>> {
+        self.synth_config.write().map_err(|_| {
+            PiperError::OperationError("Synthesis config lock is poisoned".to_string())
+        })
+    }
     fn infer_with_values_batched(
         &self,
         mut input_batches: Vec<Vec<i64>>,
     ) -> PiperResult<Vec<PiperWaveSamples>> {
-        let session = match self.get_or_create_inference_session() {
-            Ok(ref session) => session,
-            Err(err) => {
-                return Err(PiperError::OperationError(format!(
-                    "Failed to initialize onnxruntime inference session: `{}`",
-                    err
-                )))
-            }
-        };
+        let session = self.get_or_create_inference_session()?;
 
-        let synth_config = self.synth_config.read().unwrap();
+        let synth_config = self.read_synth_config()?;
 
-        let pad_input_id = self
-            .config
-            .phoneme_id_map
-            .get(&PAD)
-            .unwrap()
-            .first()
-            .unwrap();
+        let pad_input_id = self.config.symbol_id(PAD)?;
         let num_batches = input_batches.len();
         let max_len = match input_batches.iter().map(|v| v.len()).max() {
             Some(length) => length,
@@ -188,24 +518,26 @@ impl VitsModel {
             }
         };
         for input in input_batches.iter_mut() {
-            input.resize(max_len, *pad_input_id);
+            input.resize(max_len, pad_input_id);
         }
         let input_batches = Vec::from_iter(input_batches.into_iter().flatten());
-        let phoneme_inputs = CowArray::from(
-            Array2::<i64>::from_shape_vec((num_batches, max_len), input_batches).unwrap(),
-        )
-        .into_dyn();
+        let phoneme_inputs =
+            match Array2::<i64>::from_shape_vec((num_batches, max_len), input_batches) {
+                Ok(array) => CowArray::from(array).into_dyn(),
+                Err(e) => {
+                    return Err(PiperError::OperationError(format!(
+                        "Failed to build phoneme input tensor. Error: {}",
+                        e
+                    )))
+                }
+            };
 
         let input_lengths = CowArray::from(Array1::<i64>::from_iter(
             (0..num_batches).map(|_| max_len as i64),
         ))
         .into_dyn();
 
-        let scales = Array1::<f32>::from_iter([
-            synth_config.noise_scale,
-            synth_config.length_scale,
-            synth_config.noise_w,
-        ]);
+        let scales = Array1::<f32>::from_iter(synth_config.scales(self.zero_noise));
         let scales = CowArray::from(scales).into_dyn();
 
         let speaker_id = if self.config.num_speakers > 1 {
@@ -221,51 +553,70 @@ impl VitsModel {
         let timer = std::time::Instant::now();
         let outputs: Vec<Value> = {
             let mut inputs = vec![
-                Value::from_array(session.allocator(), &phoneme_inputs).unwrap(),
-                Value::from_array(session.allocator(), &input_lengths).unwrap(),
-                Value::from_array(session.allocator(), &scales).unwrap(),
+                Value::from_array(session.allocator(), &phoneme_inputs)
+                    .map_err(input_tensor_error)?,
+                Value::from_array(session.allocator(), &input_lengths)
+                    .map_err(input_tensor_error)?,
+                Value::from_array(session.allocator(), &scales).map_err(input_tensor_error)?,
             ];
             if let Some(ref sid_tensor) = speaker_id {
-                inputs.push(Value::from_array(session.allocator(), sid_tensor).unwrap());
+                inputs.push(
+                    Value::from_array(session.allocator(), sid_tensor)
+                        .map_err(input_tensor_error)?,
+                );
             }
             match session.run(inputs) {
                 Ok(out) => out,
                 Err(e) => {
-                    return Err(PiperError::OperationError(format!(
-                        "Failed to run model inference. Error: {}",
-                        e
-                    )))
+                    return Err(PiperError::InferenceFailed {
+                        message: "Failed to run model inference".to_string(),
+                        source: Some(e),
+                    })
                 }
             }
         };
         let inference_ms = timer.elapsed().as_millis() as f32;
 
-        let outputs: OrtOwnedTensor<f32, _> = match outputs[0].try_extract() {
+        let Some(output) = outputs.first() else {
+            return Err(PiperError::InferenceFailed {
+                message: "Model inference produced no output".to_string(),
+                source: None,
+            });
+        };
+        let outputs: OrtOwnedTensor<f32, _> = match output.try_extract() {
             Ok(out) => out,
             Err(e) => {
-                return Err(PiperError::OperationError(format!(
-                    "Failed to run model inference. Error: {}",
-                    e
-                )))
+                return Err(PiperError::InferenceFailed {
+                    message: "Failed to extract model inference output".to_string(),
+                    source: Some(e),
+                })
             }
         };
         let outputs = outputs.view();
-        let audio_outputs = outputs
-            .view()
-            .into_shape((num_batches, *outputs.shape().last().unwrap()))
-            .unwrap();
+        let num_samples = outputs.shape().last().copied().unwrap_or(0);
+        let audio_outputs = match outputs.view().into_shape((num_batches, num_samples)) {
+            Ok(audio) => audio,
+            Err(e) => {
+                return Err(PiperError::InferenceFailed {
+                    message: format!("Unexpected shape of model inference output. Error: {}", e),
+                    source: None,
+                })
+            }
+        };
 
         let mut samples: Vec<Vec<i16>> = Vec::with_capacity(num_batches);
         for audio in audio_outputs.rows().into_iter() {
             let Ok(min_audio_value) = audio.min() else {
-                return Err(PiperError::OperationError(
-                    "Invalid output from model inference.".to_string(),
-                ));
+                return Err(PiperError::InferenceFailed {
+                    message: "Invalid output from model inference.".to_string(),
+                    source: None,
+                });
             };
             let Ok(max_audio_value) = audio.max() else {
-                return Err(PiperError::OperationError(
-                    "Invalid output from model inference. ".to_string(),
-                ));
+                return Err(PiperError::InferenceFailed {
+                    message: "Invalid output from model inference.".to_string(),
+                    source: None,
+                });
             };
             let abs_max = max_audio_value.max(min_audio_value.abs());
             let audio_scale = MAX_WAV_VALUE / abs_max.max(0.01f32);
@@ -285,30 +636,24 @@ impl VitsModel {
         })))
     }
     fn infer_with_values(&self, input_phonemes: Vec<i64>) -> PiperWaveResult {
-        let session = match self.get_or_create_inference_session() {
-            Ok(ref session) => session,
-            Err(err) => {
+        let session = self.get_or_create_inference_session()?;
+
+        let synth_config = self.read_synth_config()?;
+
+        let input_len = input_phonemes.len();
+        let phoneme_inputs = match Array2::<i64>::from_shape_vec((1, input_len), input_phonemes) {
+            Ok(array) => CowArray::from(array).into_dyn(),
+            Err(e) => {
                 return Err(PiperError::OperationError(format!(
-                    "Failed to initialize onnxruntime inference session: `{}`",
-                    err
+                    "Failed to build phoneme input tensor. Error: {}",
+                    e
                 )))
             }
         };
 
-        let synth_config = self.synth_config.read().unwrap();
-
-        let input_len = input_phonemes.len();
-        let phoneme_inputs =
-            CowArray::from(Array2::<i64>::from_shape_vec((1, input_len), input_phonemes).unwrap())
-                .into_dyn();
-
         let input_lengths = CowArray::from(Array1::<i64>::from_iter([input_len as i64])).into_dyn();
 
-        let scales = Array1::<f32>::from_iter([
-            synth_config.noise_scale,
-            synth_config.length_scale,
-            synth_config.noise_w,
-        ]);
+        let scales = Array1::<f32>::from_iter(synth_config.scales(self.zero_noise));
         let scales = CowArray::from(scales).into_dyn();
 
         let speaker_id = if self.config.num_speakers > 1 {
@@ -324,46 +669,59 @@ impl VitsModel {
         let timer = std::time::Instant::now();
         let outputs: Vec<Value> = {
             let mut inputs = vec![
-                Value::from_array(session.allocator(), &phoneme_inputs).unwrap(),
-                Value::from_array(session.allocator(), &input_lengths).unwrap(),
-                Value::from_array(session.allocator(), &scales).unwrap(),
+                Value::from_array(session.allocator(), &phoneme_inputs)
+                    .map_err(input_tensor_error)?,
+                Value::from_array(session.allocator(), &input_lengths)
+                    .map_err(input_tensor_error)?,
+                Value::from_array(session.allocator(), &scales).map_err(input_tensor_error)?,
             ];
             if let Some(ref sid_tensor) = speaker_id {
-                inputs.push(Value::from_array(session.allocator(), sid_tensor).unwrap());
+                inputs.push(
+                    Value::from_array(session.allocator(), sid_tensor)
+                        .map_err(input_tensor_error)?,
+                );
             }
             match session.run(inputs) {
                 Ok(out) => out,
                 Err(e) => {
-                    return Err(PiperError::OperationError(format!(
-                        "Failed to run model inference. Error: {}",
-                        e
-                    )))
+                    return Err(PiperError::InferenceFailed {
+                        message: "Failed to run model inference".to_string(),
+                        source: Some(e),
+                    })
                 }
             }
         };
         let inference_ms = timer.elapsed().as_millis() as f32;
 
-        let outputs: OrtOwnedTensor<f32, _> = match outputs[0].try_extract() {
+        let Some(output) = outputs.first() else {
+            return Err(PiperError::InferenceFailed {
+                message: "Model inference produced no output".to_string(),
+                source: None,
+            });
+        };
+        let outputs: OrtOwnedTensor<f32, _> = match output.try_extract() {
             Ok(out) => out,
             Err(e) => {
-                return Err(PiperError::OperationError(format!(
-                    "Failed to run model inference. Error: {}",
-                    e
-                )))
+                return Err(PiperError::InferenceFailed {
+                    message: "Failed to extract model inference output".to_string(),
+                    source: Some(e),
+                })
             }
         };
 
         let audio_output = outputs.view();
 
         let Ok(min_audio_value) = audio_output.min() else {
-            return Err(PiperError::OperationError(
-                "Invalid output from model inference.".to_string(),
-            ));
+            return Err(PiperError::InferenceFailed {
+                message: "Invalid output from model inference.".to_string(),
+                source: None,
+            });
         };
         let Ok(max_audio_value) = audio_output.max() else {
-            return Err(PiperError::OperationError(
-                "Invalid output from model inference. ".to_string(),
-            ));
+            return Err(PiperError::InferenceFailed {
+                message: "Invalid output from model inference.".to_string(),
+                source: None,
+            });
         };
         let abs_max = max_audio_value.max(min_audio_value.abs());
         let audio_scale = MAX_WAV_VALUE / abs_max.max(0.01f32);
@@ -377,46 +735,28 @@ impl VitsModel {
             Some(inference_ms),
         ))
     }
-    fn phonemes_to_input_ids(
-        &self,
-        phonemes: &str,
-        pad_id: i64,
-        bos_id: i64,
-        eos_id: i64,
-    ) -> Vec<i64> {
-        let mut phoneme_ids: Vec<i64> = Vec::with_capacity((phonemes.len() + 1) * 2);
-        phoneme_ids.push(bos_id);
-        for phoneme in phonemes.chars() {
-            if let Some(id) = self.config.phoneme_id_map.get(&phoneme) {
-                phoneme_ids.push(*id.first().unwrap());
-                phoneme_ids.push(pad_id);
-            }
-        }
-        phoneme_ids.push(eos_id);
-        phoneme_ids
-    }
-    fn get_or_create_inference_session(&self) -> &Result<ort::Session, ort::OrtError> {
-        self.session.get_or_init(|| {
-            SessionBuilder::new(self.ort_env)?
-                .with_optimization_level(GraphOptimizationLevel::Level3)?
+    fn get_or_create_inference_session(&self) -> PiperResult<&ort::Session> {
+        let result = self.session.get_or_try_init(|| {
+            let options = &self.session_options;
+            SessionBuilder::new(&self.ort_env)?
+                .with_optimization_level(copy_optimization_level(&options.optimization_level))?
                 .with_allocator(ort::AllocatorType::Arena)?
-                .with_memory_pattern(true)?
-                .with_parallel_execution(true)?
-                .with_inter_threads(*CPU_COUNT / 2)?
-                .with_intra_threads(*CPU_COUNT / 2)?
+                .with_memory_pattern(options.memory_pattern)?
+                .with_parallel_execution(options.parallel_execution)?
+                .with_inter_threads(options.inter_threads)?
+                .with_intra_threads(options.intra_threads)?
                 .with_model_from_file(&self.onnx_path)
+        });
+        result.map_err(|e| PiperError::InferenceFailed {
+            message: format!(
+                "Failed to initialize onnxruntime inference session for `{}`",
+                self.onnx_path.display()
+            ),
+            source: Some(e),
         })
     }
     pub fn get_input_output_info(&self) -> PiperResult<Vec<String>> {
-        let session = match self.get_or_create_inference_session() {
-            Ok(ref session) => session,
-            Err(err) => {
-                return Err(PiperError::OperationError(format!(
-                    "Failed to initialize onnxruntime inference session: `{}`",
-                    err
-                )))
-            }
-        };
+        let session = self.get_or_create_inference_session()?;
         Ok(session
             .inputs
             .iter()
@@ -432,27 +772,97 @@ impl VitsModel {
             })
             .collect())
     }
+    /// Checks that the inputs of the onnx graph match what the model config promises
+    fn validate_graph(&self) -> PiperResult<()> {
+        if !self.onnx_path.exists() {
+            return Err(PiperError::VoiceNotFound {
+                path: self.onnx_path.clone(),
+                source: None,
+            });
+        }
+        let session = self.get_or_create_inference_session()?;
+        let mut expected_inputs = vec![
+            (INPUT_PHONEMES, TensorElementDataType::Int64),
+            (INPUT_LENGTHS, TensorElementDataType::Int64),
+            (INPUT_SCALES, TensorElementDataType::Float32),
+        ];
+        if self.config.num_speakers > 1 {
+            expected_inputs.push((INPUT_SPEAKER_ID, TensorElementDataType::Int64));
+        }
+        let has_sid_input = session.inputs.iter().any(|i| i.name == INPUT_SPEAKER_ID);
+        if has_sid_input && self.config.num_speakers <= 1 {
+            return Err(PiperError::InvalidConfig {
+                message: format!(
+                    "Model `{}` expects a speaker id but its config declares `num_speakers` = {}",
+                    self.onnx_path.display(),
+                    self.config.num_speakers
+                ),
+                source: None,
+            });
+        }
+        if session.inputs.len() != expected_inputs.len() {
+            return Err(PiperError::InvalidConfig {
+                message: format!(
+                    "Model `{}` has {} inputs, but {} were expected according to its config. Inputs: {}",
+                    self.onnx_path.display(),
+                    session.inputs.len(),
+                    expected_inputs.len(),
+                    self.get_input_output_info()?.join(", ")
+                ),
+                source: None,
+            });
+        }
+        for (input, (name, input_type)) in session.inputs.iter().zip(expected_inputs) {
+            if input.name != name || input.input_type != input_type {
+                return Err(PiperError::InvalidConfig {
+                    message: format!(
+                        "Model `{}` has input `{}` of type `{:?}`, expected `{}` of type `{:?}`",
+                        self.onnx_path.display(),
+                        input.name,
+                        input.input_type,
+                        name,
+                        input_type
+                    ),
+                    source: None,
+                });
+            }
+        }
+        Ok(())
+    }
     fn load_model_config(config_path: &PathBuf) -> PiperResult<(ModelConfig, SynthesisConfig)> {
         let file = match File::open(config_path) {
             Ok(file) => file,
+            Err(why) if why.kind() == std::io::ErrorKind::NotFound => {
+                return Err(PiperError::VoiceNotFound {
+                    path: config_path.clone(),
+                    source: Some(why),
+                })
+            }
             Err(why) => {
-                return Err(PiperError::FailedToLoadResource(format!(
-                    "Faild to load model config: `{}`. Caused by: `{}`",
-                    config_path.display(),
-                    why
-                )))
+                return Err(PiperError::Io {
+                    message: format!("Failed to open model config: `{}`", config_path.display()),
+                    source: Some(why),
+                })
             }
         };
         let model_config: ModelConfig = match serde_json::from_reader(file) {
             Ok(config) => config,
             Err(why) => {
-                return Err(PiperError::FailedToLoadResource(format!(
-                    "Faild to parse model config from file: `{}`. Caused by: `{}`",
-                    config_path.display(),
-                    why
-                )))
+                return Err(PiperError::InvalidConfig {
+                    message: format!(
+                        "Failed to parse model config from file: `{}`",
+                        config_path.display()
+                    ),
+                    source: Some(why),
+                })
             }
         };
+        if let Err(why) = model_config.validate() {
+            return Err(PiperError::InvalidConfig {
+                message: format!("`{}`: {}", config_path.display(), why),
+                source: None,
+            });
+        }
         let synth_config = SynthesisConfig {
             speaker: None,
             noise_scale: model_config.inference.noise_scale,
@@ -465,79 +875,24 @@ impl VitsModel {
 
 impl PiperModel for VitsModel {
     fn phonemize_text(&self, text: &str) -> PiperResult<Phonemes> {
-        if let Some(phoneme_type) = &self.config.phoneme_type {
-            if phoneme_type == "text" {
-                return Ok(Phonemes(vec![text.to_string()]));
-            }
-        }
-
-        let phonemes = match text_to_phonemes(text, &self.config.espeak.voice, None) {
-            Ok(ph) => ph,
-            Err(e) => {
-                return Err(PiperError::PhonemizationError(format!(
-                    "Failed to phonemize given text using espeak-ng. Error: {}",
-                    e
-                )))
-            }
-        };
+        self.phonemizer.phonemize(text, &self.config.espeak.voice)
+    }
 
-        Ok(phonemes.into())
+    fn phonemize_text_with_language(&self, text: &str, language: &str) -> PiperResult<Phonemes> {
+        self.phonemizer.phonemize(text, language)
     }
 
     fn speak_batch(&self, phoneme_batches: Vec<String>) -> PiperResult<Vec<PiperWaveSamples>> {
-        let pad_id = *self
-            .config
-            .phoneme_id_map
-            .get(&PAD)
-            .unwrap()
-            .first()
-            .unwrap();
-        let bos_id = *self
-            .config
-            .phoneme_id_map
-            .get(&BOS)
-            .unwrap()
-            .first()
-            .unwrap();
-        let eos_id = *self
-            .config
-            .phoneme_id_map
-            .get(&EOS)
-            .unwrap()
-            .first()
-            .unwrap();
-        let phoneme_batches = Vec::from_iter(
-            phoneme_batches
-                .into_iter()
-                .map(|batch| self.phonemes_to_input_ids(&batch, pad_id, bos_id, eos_id)),
-        );
+        let phoneme_batches = phoneme_batches
+            .iter()
+            .map(|batch| self.phoneme_ids(batch))
+            .collect::<PiperResult<Vec<_>>>()?;
         self.infer_with_values_batched(phoneme_batches)
     }
 
     fn speak_one_sentence(&self, phonemes: String) -> PiperWaveResult {
-        let pad_id = *self
-            .config
-            .phoneme_id_map
-            .get(&PAD)
-            .unwrap()
-            .first()
-            .unwrap();
-        let bos_id = *self
-            .config
-            .phoneme_id_map
-            .get(&BOS)
-            .unwrap()
-            .first()
-            .unwrap();
-        let eos_id = *self
-            .config
-            .phoneme_id_map
-            .get(&EOS)
-            .unwrap()
-            .first()
-            .unwrap();
-        let phonemes = self.phonemes_to_input_ids(&phonemes, pad_id, bos_id, eos_id);
-        self.infer_with_values(phonemes)
+        let phoneme_ids = self.phoneme_ids(&phonemes)?;
+        self.infer_with_values(phoneme_ids)
     }
 
     fn wave_info(&self) -> PiperResult<PiperWaveInfo> {
@@ -547,6 +902,76 @@ impl PiperModel for VitsModel {
             sample_width: 2usize,
         })
     }
+
+    fn language(&self) -> Option<String> {
+        Some(self.config.espeak.voice.clone())
+    }
+
+    /// Replacing the onnx file changes its size or modification time, so audio of the old voice is not reused.
+    /// Phonemizer settings are not part of it, caches shared by differently built models must be kept apart.
+    fn synthesis_fingerprint(&self) -> Option<String> {
+        let metadata = std::fs::metadata(&self.onnx_path).ok()?;
+        let modified = metadata
+            .modified()
+            .ok()?
+            .duration_since(std::time::UNIX_EPOCH)
+            .ok()?;
+        let synth_config = self.read_synth_config().ok()?;
+        let speaker_id = synth_config.speaker.as_ref().map_or(0, |(_, sid)| *sid);
+        Some(format!(
+            "vits;{};{};{};speaker={};scales={:?}",
+            self.onnx_path.display(),
+            metadata.len(),
+            modified.as_nanos(),
+            speaker_id,
+            synth_config.scales(self.zero_noise)
+        ))
+    }
+
+    fn without_noise(&self) -> Option<Arc<dyn PiperModel + Sync + Send>> {
+        Some(Arc::new(VitsModel {
+            synth_config: self.synth_config.clone(),
+            config: self.config.clone(),
+            speaker_map: self.speaker_map.clone(),
+            onnx_path: self.onnx_path.clone(),
+            ort_env: self.ort_env.clone(),
+            session_options: self.session_options.clone(),
+            session: self.session.clone(),
+            phonemizer: self.phonemizer.clone(),
+            phoneme_cache: self.phoneme_cache.clone(),
+            strict_phonemes: self.strict_phonemes,
+            zero_noise: true,
+        }))
+    }
+}
+
+/// Byte offsets at which the characters of `phonemes` end, a character followed by combining marks ends after its last mark
+fn cluster_ends(phonemes: &str) -> Vec<usize> {
+    let mut ends = Vec::with_capacity(phonemes.len());
+    for (i, c) in phonemes.char_indices() {
+        if i > 0 && !is_combining_mark(c) {
+            ends.push(i);
+        }
+    }
+    if !phonemes.is_empty() {
+        ends.push(phonemes.len());
+    }
+    ends
+}
+
+/// Combining diacritical marks as used in IPA transcriptions, including the tie bars
+fn is_combining_mark(c: char) -> bool {
+    matches!(
+        c as u32,
+        0x0300..=0x036F | 0x1AB0..=0x1AFF | 0x1DC0..=0x1DFF | 0x20D0..=0x20FF | 0xFE20..=0xFE2F
+    )
+}
+
+fn input_tensor_error(error: ort::OrtError) -> PiperError {
+    PiperError::InferenceFailed {
+        message: "Failed to create input tensor".to_string(),
+        source: Some(error),
+    }
 }
 
 fn reversed_mapping<K, V>(input: &HashMap<K, V>) -> HashMap<V, K>
@@ -556,3 +981,333 @@ where
 {
     HashMap::from_iter(input.iter().map(|(k, v)| (v.to_owned(), k.to_owned())))
 }
+
+/// Fails on an `espeak.voice` missing from eSpeak-ng's data directory,
+/// which would otherwise only be reported when synthesizing
+fn check_espeak_voice(voice: &str) -> PiperResult<()> {
+    if find_espeak_voice(voice)?.is_some() {
+        return Ok(());
+    }
+    let primary_language = voice.split(['-', '_', '+']).next().unwrap_or_default();
+    let mut similar = Vec::from_iter(
+        list_espeak_voices()?
+            .into_iter()
+            .flat_map(|voice| voice.languages)
+            .filter(|language| {
+                language
+                    .split('-')
+                    .next()
+                    .is_some_and(|primary| primary.eq_ignore_ascii_case(primary_language))
+            }),
+    );
+    similar.sort();
+    similar.dedup();
+    let hint = match similar.is_empty() {
+        true => String::new(),
+        false => format!(", similar voices: {}", similar.join(", ")),
+    };
+    Err(PiperError::InvalidConfig {
+        message: format!("eSpeak-ng voice `{}` is not available{}", voice, hint),
+        source: None,
+    })
+}
+
+/// `GraphOptimizationLevel` is neither `Copy` nor `Clone`
+fn copy_optimization_level(level: &GraphOptimizationLevel) -> GraphOptimizationLevel {
+    match level {
+        GraphOptimizationLevel::Disable => GraphOptimizationLevel::Disable,
+        GraphOptimizationLevel::Level1 => GraphOptimizationLevel::Level1,
+        GraphOptimizationLevel::Level2 => GraphOptimizationLevel::Level2,
+        GraphOptimizationLevel::Level3 => GraphOptimizationLevel::Level3,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config_json(phoneme_id_map: &str, num_symbols: u32, num_speakers: u32) -> String {
+        config_json_with_map("{}", phoneme_id_map, num_symbols, num_speakers)
+    }
+
+    fn config_json_with_map(
+        phoneme_map: &str,
+        phoneme_id_map: &str,
+        num_symbols: u32,
+        num_speakers: u32,
+    ) -> String {
+        format!(
+            r#"{{
+                "audio": {{"sample_rate": 22050}},
+                "num_speakers": {},
+                "speaker_id_map": {{}},
+                "espeak": {{"voice": "en-us"}},
+                "inference": {{"noise_scale": 0.667, "length_scale": 1, "noise_w": 0.8}},
+                "num_symbols": {},
+                "phoneme_type": "espeak",
+                "phoneme_map": {},
+                "phoneme_id_map": {}
+            }}"#,
+            num_speakers, num_symbols, phoneme_map, phoneme_id_map
+        )
+    }
+
+    fn model_config(phoneme_id_map: &str, num_symbols: u32, num_speakers: u32) -> ModelConfig {
+        serde_json::from_str(&config_json(phoneme_id_map, num_symbols, num_speakers)).unwrap()
+    }
+
+    /// Voice used by tests that need a real onnx model, can be overridden with `PIPER_TEST_VOICE`
+    fn test_voice() -> VitsModel {
+        let onnx_path = match std::env::var("PIPER_TEST_VOICE") {
+            Ok(path) => PathBuf::from(path),
+            Err(_) => PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+                .join("../piper-test/piper-voices/ru/ru_RU/irina/medium/ru_RU-irina-medium.onnx"),
+        };
+        let config_path = PathBuf::from(format!("{}.json", onnx_path.display()));
+        assert!(
+            onnx_path.exists() && config_path.exists(),
+            "Test voice `{}` not found, set `PIPER_TEST_VOICE` to an onnx voice with its config",
+            onnx_path.display()
+        );
+        VitsModel::builder(config_path, onnx_path).build().unwrap()
+    }
+
+    /// Xorshift generator, keeps the property tests reproducible without extra dependencies
+    struct XorShift(u64);
+
+    impl XorShift {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+        fn below(&mut self, n: usize) -> usize {
+            (self.next() % n as u64) as usize
+        }
+    }
+
+    /// Random phoneme input mixing known symbols, combining marks, control characters and arbitrary code points
+    fn random_phonemes(rng: &mut XorShift, symbols: &[char], max_len: usize) -> String {
+        let len = rng.below(max_len + 1);
+        (0..len)
+            .map(|_| match rng.below(6) {
+                0 | 1 => symbols[rng.below(symbols.len())],
+                2 => char::from_u32(0x0300 + rng.below(0x70) as u32).unwrap(),
+                3 => ['\0', '\n', '\t', ' ', '\u{feff}'][rng.below(5)],
+                _ => char::from_u32(rng.below(0x110000) as u32).unwrap_or('\u{fffd}'),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_phoneme_ids_never_panic() {
+        let config = model_config(r#"{"_": [0], "^": [1], "$": [2], "a": [3], "ˈ": []}"#, 4, 1);
+        let symbols = ['_', '^', '$', 'a', 'ˈ'];
+        let mut rng = XorShift(0x9E3779B97F4A7C15);
+        for _ in 0..1000 {
+            let phonemes = random_phonemes(&mut rng, &symbols, 256);
+            let ids = config.phonemes_to_input_ids(&phonemes, false).unwrap();
+            assert_eq!(ids.first(), Some(&1));
+            assert_eq!(ids.last(), Some(&2));
+            if let Err(e) = config.phonemes_to_input_ids(&phonemes, true) {
+                assert!(matches!(e, PiperError::UnknownPhonemes(_)));
+            }
+        }
+    }
+
+    #[test]
+    fn test_strict_mode_lists_unknown_phonemes() {
+        let config = model_config(r#"{"_": [0], "^": [1], "$": [2], "a": [3]}"#, 4, 1);
+        assert_eq!(
+            config.phonemes_to_input_ids("aa", true).unwrap(),
+            [1, 3, 0, 3, 0, 2]
+        );
+        match config.phonemes_to_input_ids("axyxa", true) {
+            Err(PiperError::UnknownPhonemes(phonemes)) => assert_eq!(phonemes, ["x", "y"]),
+            other => panic!(
+                "Expected unknown phonemes, got {:?}",
+                other.map_err(|e| e.to_string())
+            ),
+        }
+        assert_eq!(
+            config.phonemes_to_input_ids("axa", false).unwrap(),
+            [1, 3, 0, 3, 0, 2]
+        );
+    }
+
+    /// Voice with its own symbol set: `g` instead of eSpeak's `ɡ`, a tied affricate,
+    /// a nasal vowel only known as a whole and a stress mark with two ids
+    fn mapped_config() -> ModelConfig {
+        let json = config_json_with_map(
+            r#"{"ɡ": ["g"], "ʧ": ["t͡ʃ"], "ɚ": ["ə", "ɹ"]}"#,
+            r#"{"_": [0], "^": [1], "$": [2], "g": [3], "t͡ʃ": [4], "ã": [5], "a": [6],
+                "̃": [7], "ə": [8], "ɹ": [9], "ˈ": [10, 11], "t": [12]}"#,
+            13,
+            1,
+        );
+        let config: ModelConfig = serde_json::from_str(&json).unwrap();
+        config.validate().unwrap();
+        config
+    }
+
+    #[test]
+    fn test_phoneme_map_is_applied() {
+        let config = mapped_config();
+        assert_eq!(
+            config.phonemes_to_input_ids("ɡʧɚ", true).unwrap(),
+            [1, 3, 0, 4, 0, 8, 0, 9, 0, 2]
+        );
+    }
+
+    #[test]
+    fn test_multi_codepoint_phonemes() {
+        let config = mapped_config();
+        // Tied affricate as one phoneme, not `t` followed by an unknown tie bar
+        assert_eq!(
+            config.phonemes_to_input_ids("t͡ʃ", true).unwrap(),
+            [1, 4, 0, 2]
+        );
+        // Every id of a phoneme is followed by a pad
+        assert_eq!(
+            config.phonemes_to_input_ids("ˈa", true).unwrap(),
+            [1, 10, 0, 11, 0, 6, 0, 2]
+        );
+    }
+
+    #[test]
+    fn test_combining_diacritics() {
+        let config = mapped_config();
+        // Precomposed key wins over its parts
+        assert_eq!(
+            config.phonemes_to_input_ids("ã", true).unwrap(),
+            [1, 5, 0, 2]
+        );
+        // Base and mark known separately
+        assert_eq!(
+            config.phonemes_to_input_ids("ə̃", true).unwrap(),
+            [1, 8, 0, 7, 0, 2]
+        );
+        // An unknown base is reported together with its marks
+        match config.phonemes_to_input_ids("ɛ̃a", true) {
+            Err(PiperError::UnknownPhonemes(phonemes)) => assert_eq!(phonemes, ["ɛ̃"]),
+            other => panic!(
+                "Expected unknown phonemes, got {:?}",
+                other.map_err(|e| e.to_string())
+            ),
+        }
+        assert_eq!(
+            config.phonemes_to_input_ids("ɛ̃a", false).unwrap(),
+            [1, 6, 0, 2]
+        );
+    }
+
+    #[test]
+    fn test_phoneme_map_target_must_exist() {
+        let json =
+            config_json_with_map(r#"{"ɡ": ["g"]}"#, r#"{"_": [0], "^": [1], "$": [2]}"#, 3, 1);
+        let config: ModelConfig = serde_json::from_str(&json).unwrap();
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_known_punctuation() {
+        let config = model_config(
+            r#"{"_": [0], "^": [1], "$": [2], ".": [3], ";": [4], "…": [5], "*": [6]}"#,
+            7,
+            1,
+        );
+        assert_eq!(config.known_punctuation(), ".;…");
+    }
+
+    #[test]
+    fn test_check_phoneme_ids() {
+        let config = model_config(r#"{"_": [0], "^": [1], "$": [2], "a": [3]}"#, 4, 1);
+        assert!(config.check_phoneme_ids(&[1, 3, 0, 2]).is_ok());
+        assert!(config.check_phoneme_ids(&[1, 4, 2]).is_err());
+        assert!(config.check_phoneme_ids(&[-1]).is_err());
+        assert!(config.check_phoneme_ids(&[]).is_err());
+        let unknown_symbols = model_config(r#"{"_": [0], "^": [1], "$": [2], "a": [3]}"#, 0, 1);
+        assert!(unknown_symbols.check_phoneme_ids(&[1, 40, 0, 2]).is_ok());
+        assert!(unknown_symbols.check_phoneme_ids(&[1, -1, 2]).is_err());
+    }
+
+    #[test]
+    #[ignore = "needs an onnx voice, see `test_voice`"]
+    fn test_speak_one_sentence_never_panics() {
+        let model = test_voice();
+        let symbols = Vec::from_iter(
+            model
+                .config
+                .phoneme_id_map
+                .keys()
+                .filter_map(|phoneme| phoneme.chars().next()),
+        );
+        let mut rng = XorShift(0x2545F4914F6CDD1D);
+        for _ in 0..20 {
+            let phonemes = random_phonemes(&mut rng, &symbols, 64);
+            // Any outcome is acceptable as long as it is reported through `PiperResult`
+            let _ = model.speak_one_sentence(phonemes);
+        }
+    }
+
+    #[test]
+    fn test_missing_onnx_file() {
+        let dir = crate::test_util::temp_path("vits");
+        std::fs::create_dir_all(&dir).unwrap();
+        let config_path = dir.join("voice.onnx.json");
+        let json = config_json(r#"{"_": [0], "^": [1], "$": [2]}"#, 3, 1);
+        std::fs::write(&config_path, json).unwrap();
+        let result = VitsModel::builder(config_path, dir.join("voice.onnx")).build();
+        std::fs::remove_dir_all(&dir).ok();
+        assert_eq!(
+            result.err().map(|e| e.kind()),
+            Some(crate::core::PiperErrorKind::VoiceNotFound)
+        );
+    }
+
+    #[test]
+    fn test_unknown_espeak_voice() {
+        let dir = crate::test_util::temp_path("voice");
+        std::fs::create_dir_all(&dir).unwrap();
+        let config_path = dir.join("voice.onnx.json");
+        let json = config_json(r#"{"_": [0], "^": [1], "$": [2]}"#, 3, 1)
+            .replace(r#""voice": "en-us""#, r#""voice": "en-uss""#);
+        std::fs::write(&config_path, json).unwrap();
+        let result = VitsModel::builder(config_path, dir.join("voice.onnx")).build();
+        std::fs::remove_dir_all(&dir).ok();
+        match result {
+            Err(PiperError::InvalidConfig { message, .. }) => {
+                assert!(message.contains("`en-uss`"), "{}", message);
+                assert!(
+                    message.contains("en-us,") || message.ends_with("en-us"),
+                    "{}",
+                    message
+                );
+            }
+            other => panic!(
+                "Expected an invalid config, got {:?}",
+                other.map(|_| ()).map_err(|e| e.to_string())
+            ),
+        }
+    }
+
+    #[test]
+    fn test_valid_config() {
+        let config = model_config(r#"{"_": [0], "^": [1], "$": [2], "a": [3]}"#, 4, 1);
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_missing_required_symbol() {
+        let config = model_config(r#"{"_": [0], "^": [1], "a": [3]}"#, 4, 1);
+        let err = config.validate().unwrap_err();
+        assert!(err.contains("`$`"), "{}", err);
+    }
+
+    #[test]
+    fn test_id_out_of_range() {
+        let config = model_config(r#"{"_": [0], "^": [1], "$": [2], "a": [4]}"#, 4, 1);
+        assert!(config.validate().is_err());
+    }
+}
@@ -1,15 +1,39 @@
-use std::{fmt, fs::File, io::prelude::*, path::PathBuf};
+use std::{fmt, fs::File, io, io::prelude::*, path::PathBuf};
 
-use riff_wave::WaveWriter;
+use riff_wave::{WaveWriter, WriteError};
 
 #[derive(Debug)]
-pub struct WaveWriterError(String);
+pub enum WaveWriterError {
+    UnsupportedFormat(String),
+    Io {
+        message: String,
+        source: Option<io::Error>,
+    },
+}
 
-impl std::error::Error for WaveWriterError {}
+impl WaveWriterError {
+    fn io(message: String, source: Option<io::Error>) -> Self {
+        Self::Io { message, source }
+    }
+}
+
+impl std::error::Error for WaveWriterError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io {
+                source: Some(e), ..
+            } => Some(e),
+            _ => None,
+        }
+    }
+}
 
 impl fmt::Display for WaveWriterError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        self.0.fmt(f)
+        match self {
+            Self::UnsupportedFormat(msg) => msg.fmt(f),
+            Self::Io { message, .. } => message.fmt(f),
+        }
     }
 }
 
@@ -30,20 +54,30 @@ where
         (sample_width * 8) as u16,
         buf,
     ) else {
-        return Err(WaveWriterError(
-            "Failed to initialize wave writer".to_string(),
-        ));
+        return Err(WaveWriterError::UnsupportedFormat(format!(
+            "Failed to initialize wave writer for {} channel(s), {} Hz, {} byte samples",
+            num_channels, sample_rate, sample_width
+        )));
     };
-    let any_fail = samples
-        .map(|i| wave_writer.write_sample_i16(*i))
-        .any(|r| r.is_err());
-    if any_fail {
-        return Err(WaveWriterError("Failed to write wave samples".to_string()));
-    }
-    if wave_writer.sync_header().is_err() {
-        return Err(WaveWriterError("Failed to update wave header".to_string()));
+    for sample in samples {
+        match wave_writer.write_sample_i16(*sample) {
+            Ok(_) => (),
+            Err(WriteError::Io(e)) => {
+                return Err(WaveWriterError::io(
+                    "Failed to write wave samples".to_string(),
+                    Some(e),
+                ))
+            }
+            Err(WriteError::ExceededMaxSize) => {
+                return Err(WaveWriterError::UnsupportedFormat(
+                    "Wave samples exceed the 4 GiB limit of wave files".to_string(),
+                ))
+            }
+        }
     }
-    Ok(())
+    wave_writer
+        .sync_header()
+        .map_err(|e| WaveWriterError::io("Failed to update wave header".to_string(), Some(e)))
 }
 
 pub fn write_wave_samples_to_file<'a, I>(
@@ -65,21 +99,22 @@ where
         sample_width,
     )?;
     match File::create(&filename) {
-        Ok(mut file) => match file.write(out.as_slice()) {
+        Ok(mut file) => match file.write_all(out.as_slice()) {
             Ok(_) => Ok(()),
             Err(e) => {
                 std::fs::remove_file(PathBuf::from(&filename)).ok();
-                Err(WaveWriterError(format!(
-                    "Failed to write wave bytes to file `{}`. Error: {}",
-                    filename.display(),
-                    e
-                )))
+                Err(WaveWriterError::io(
+                    format!(
+                        "Failed to write wave bytes to file `{}`",
+                        filename.display()
+                    ),
+                    Some(e),
+                ))
             }
         },
-        Err(e) => Err(WaveWriterError(format!(
-            "Failed to create file `{}` for writing. Error: {}",
-            filename.display(),
-            e
-        ))),
+        Err(e) => Err(WaveWriterError::io(
+            format!("Failed to create file `{}` for writing", filename.display()),
+            Some(e),
+        )),
     }
 }